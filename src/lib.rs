@@ -10,14 +10,58 @@ use lib_plugin_abi::{
 
 /// Plugin-specific CLI service ID
 const SERVICE_CLI: &str = "adi.tasks.cli";
-use once_cell::sync::OnceCell;
+/// Prompt templates service ID
+const SERVICE_PROMPTS: &str = "adi.tasks.prompts";
+
+/// Environment variable naming the project to open at init instead of the global store
+const PROJECT_ENV: &str = "ADI_TASKS_PROJECT";
+
+/// Format identifier written into export bundles
+const BUNDLE_FORMAT: &str = "adi.tasks.bundle";
+/// Newest bundle schema version this plugin can read and write
+const BUNDLE_SCHEMA_VERSION: u64 = 1;
+/// Most commands a single batch may contain
+const MAX_BATCH_SIZE: usize = 100;
 use serde_json::json;
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
 use std::ffi::c_void;
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use adi_tasks_core::{CreateTask, Task, TaskId, TaskManager, TaskStatus};
+
+static TASKS: RwLock<Option<TaskManager>> = RwLock::new(None);
+
+/// Project the active manager was opened for; `None` while serving the global store
+static PROJECT_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Why the last attempt to open a store failed, reported while no manager is active
+static OPEN_ERROR: RwLock<Option<String>> = RwLock::new(None);
+
+/// Extra status names mapped onto the built-in statuses, keyed by normalized name
+static STATUS_ALIASES: RwLock<Vec<(String, TaskStatus)>> = RwLock::new(Vec::new());
+
+/// Allowed targets per source status; statuses without an entry may move anywhere
+static TRANSITION_RULES: RwLock<Vec<(TaskStatus, Vec<TaskStatus>)>> = RwLock::new(Vec::new());
 
-use adi_tasks_core::{CreateTask, TaskId, TaskManager, TaskStatus};
+/// How many times a write is retried when the store reports a transient lock error
+static WRITE_RETRIES: AtomicU32 = AtomicU32::new(3);
 
-static TASKS: OnceCell<Option<TaskManager>> = OnceCell::new();
+/// Reject `--options` a command's usage doesn't list instead of ignoring them
+static STRICT_OPTIONS: AtomicBool = AtomicBool::new(false);
+
+/// Return command failures as a JSON payload with a stable `code` instead of plain text
+static STRUCTURED_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// Largest JSON output in bytes before lists are truncated; 0 means unlimited
+static MAX_OUTPUT_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Longest task title accepted, in characters
+static MAX_TITLE_LENGTH: AtomicUsize = AtomicUsize::new(200);
 
 // === Plugin VTable Implementation ===
 
@@ -29,11 +73,26 @@ extern "C" fn plugin_info() -> PluginInfo {
 }
 
 extern "C" fn plugin_init(ctx: *mut PluginContext) -> i32 {
-    let _ = TASKS.set(TaskManager::open_global().ok());
+    let project = env_project();
+    let open_error = open_store(project.clone());
 
     unsafe {
         let host = (*ctx).host();
 
+        let store = match project.as_ref() {
+            Some(path) => format!(
+                "project task store at {} (from {})",
+                path.display(),
+                PROJECT_ENV
+            ),
+            None => "global task store".to_string(),
+        };
+        // Keep going so the service is still registered and can report the failure
+        match open_error {
+            Some(ref e) => host.error(&format!("Failed to open {}: {}", store, e)),
+            None => host.info(&format!("Opened {}", store)),
+        }
+
         // Register CLI commands service
         let cli_descriptor =
             ServiceDescriptor::new(SERVICE_CLI, ServiceVersion::new(1, 0, 0), "adi.tasks")
@@ -53,12 +112,57 @@ extern "C" fn plugin_init(ctx: *mut PluginContext) -> i32 {
             return code;
         }
 
+        // Register prompt templates service
+        let prompts_descriptor =
+            ServiceDescriptor::new(SERVICE_PROMPTS, ServiceVersion::new(1, 0, 0), "adi.tasks")
+                .with_description("Prompt templates filled in with live task data");
+
+        let prompts_handle = ServiceHandle::new(
+            SERVICE_PROMPTS,
+            ctx as *const c_void,
+            &PROMPTS_SERVICE_VTABLE as *const ServiceVTable,
+        );
+
+        if let Err(code) = host.register_svc(prompts_descriptor, prompts_handle) {
+            host.error(&format!("Failed to register prompts service: {}", code));
+            return code;
+        }
+
         host.info("ADI Tasks plugin initialized");
     }
 
     0
 }
 
+/// Project path from the environment; unset or empty means the global store
+fn env_project() -> Option<PathBuf> {
+    std::env::var_os(PROJECT_ENV)
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Open the project's store, or the global one, and make it the active manager
+///
+/// The outcome is recorded in `TASKS`, `PROJECT_PATH` and `OPEN_ERROR`; returns why
+/// opening failed, if it did.
+fn open_store(project: Option<PathBuf>) -> Option<String> {
+    let opened = match project.as_ref() {
+        Some(path) => TaskManager::open(path),
+        None => TaskManager::open_global(),
+    };
+    let open_error = opened.as_ref().err().map(|e| e.to_string());
+    if let Ok(mut guard) = TASKS.write() {
+        *guard = opened.ok();
+    }
+    if let Ok(mut guard) = PROJECT_PATH.write() {
+        *guard = project;
+    }
+    if let Ok(mut guard) = OPEN_ERROR.write() {
+        guard.clone_from(&open_error);
+    }
+    open_error
+}
+
 extern "C" fn plugin_cleanup(_ctx: *mut PluginContext) {}
 
 extern "C" fn handle_message(
@@ -66,16 +170,144 @@ extern "C" fn handle_message(
     msg_type: RStr<'_>,
     msg_data: RStr<'_>,
 ) -> RResult<RString, PluginError> {
+    catch_panics(
+        || dispatch_message(msg_type, msg_data),
+        |message| PluginError::new(1, message),
+    )
+}
+
+fn dispatch_message(msg_type: RStr<'_>, msg_data: RStr<'_>) -> RResult<RString, PluginError> {
     match msg_type.as_str() {
         "set_project_path" => {
             let path = PathBuf::from(msg_data.as_str());
             match TaskManager::open(&path) {
-                Ok(_) => RResult::ROk(RString::from("ok")),
+                Ok(manager) => {
+                    let (Ok(mut tasks), Ok(mut project)) = (TASKS.write(), PROJECT_PATH.write())
+                    else {
+                        return RResult::RErr(PluginError::new(
+                            1,
+                            "Tasks lock poisoned".to_string(),
+                        ));
+                    };
+                    *tasks = Some(manager);
+                    *project = Some(path);
+                    if let Ok(mut open_error) = OPEN_ERROR.write() {
+                        *open_error = None;
+                    }
+                    RResult::ROk(RString::from("ok"))
+                }
                 Err(e) => {
                     RResult::RErr(PluginError::new(1, format!("Failed to open tasks: {}", e)))
                 }
             }
         }
+        // Empty while serving the global store
+        "get_project_path" => match PROJECT_PATH.read() {
+            Ok(project) => RResult::ROk(RString::from(
+                project
+                    .as_ref()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_default(),
+            )),
+            Err(_) => RResult::RErr(PluginError::new(1, "Tasks lock poisoned".to_string())),
+        },
+        // Never fails, so hosts can tell a closed store apart from an unloaded plugin
+        "ping" => {
+            let guard = TASKS.read().ok();
+            let manager = guard.as_ref().and_then(|g| g.as_ref());
+            let task_count = manager.and_then(|m| m.list().ok()).map(|t| t.len());
+            let health = json!({
+                "ok": true,
+                "store_open": manager.is_some(),
+                "task_count": task_count,
+                "version": env!("CARGO_PKG_VERSION")
+            });
+            RResult::ROk(RString::from(health.to_string()))
+        }
+        "describe_commands" => RResult::ROk(RString::from(
+            serde_json::to_string(&list_commands_json()).unwrap_or_default(),
+        )),
+        "reload" => {
+            // Lock in the same order as set_project_path so the two can't deadlock
+            let (Ok(mut tasks), Ok(project)) = (TASKS.write(), PROJECT_PATH.read()) else {
+                return RResult::RErr(PluginError::new(1, "Tasks lock poisoned".to_string()));
+            };
+            let reopened = match project.as_ref() {
+                Some(path) => TaskManager::open(path),
+                None => TaskManager::open_global(),
+            };
+            match reopened {
+                Ok(manager) => {
+                    *tasks = Some(manager);
+                    if let Ok(mut open_error) = OPEN_ERROR.write() {
+                        *open_error = None;
+                    }
+                    RResult::ROk(RString::from("ok"))
+                }
+                Err(e) => RResult::RErr(PluginError::new(
+                    1,
+                    format!("Failed to reload tasks: {}", e),
+                )),
+            }
+        }
+        "set_write_retries" => match msg_data.as_str().trim().parse::<u32>() {
+            Ok(retries) => {
+                WRITE_RETRIES.store(retries, Ordering::Relaxed);
+                RResult::ROk(RString::from("ok"))
+            }
+            Err(_) => RResult::RErr(PluginError::new(
+                1,
+                format!("Invalid retry count: {}", msg_data.as_str()),
+            )),
+        },
+        "set_strict_options" => match msg_data.as_str().trim().parse::<bool>() {
+            Ok(strict) => {
+                STRICT_OPTIONS.store(strict, Ordering::Relaxed);
+                RResult::ROk(RString::from("ok"))
+            }
+            Err(_) => RResult::RErr(PluginError::new(
+                1,
+                format!("Invalid strict flag: {}", msg_data.as_str()),
+            )),
+        },
+        "set_structured_errors" => match msg_data.as_str().trim().parse::<bool>() {
+            Ok(structured) => {
+                STRUCTURED_ERRORS.store(structured, Ordering::Relaxed);
+                RResult::ROk(RString::from("ok"))
+            }
+            Err(_) => RResult::RErr(PluginError::new(
+                1,
+                format!("Invalid structured errors flag: {}", msg_data.as_str()),
+            )),
+        },
+        "set_status_aliases" => match set_status_aliases(msg_data.as_str()) {
+            Ok(()) => RResult::ROk(RString::from("ok")),
+            Err(e) => RResult::RErr(PluginError::new(1, e)),
+        },
+        "set_transition_rules" => match set_transition_rules(msg_data.as_str()) {
+            Ok(()) => RResult::ROk(RString::from("ok")),
+            Err(e) => RResult::RErr(PluginError::new(1, e)),
+        },
+        "set_max_output_bytes" => match msg_data.as_str().trim().parse::<usize>() {
+            Ok(max) => {
+                MAX_OUTPUT_BYTES.store(max, Ordering::Relaxed);
+                RResult::ROk(RString::from("ok"))
+            }
+            Err(_) => RResult::RErr(PluginError::new(
+                1,
+                format!("Invalid output size: {}", msg_data.as_str()),
+            )),
+        },
+        "set_max_title_length" => match msg_data.as_str().trim().parse::<usize>() {
+            Ok(max) if max > 0 => {
+                MAX_TITLE_LENGTH.store(max, Ordering::Relaxed);
+                RResult::ROk(RString::from("ok"))
+            }
+            _ => RResult::RErr(PluginError::new(
+                1,
+                format!("Invalid title length: {}", msg_data.as_str()),
+            )),
+        },
         _ => RResult::RErr(PluginError::new(
             -1,
             format!("Unknown message type: {}", msg_type.as_str()),
@@ -110,33 +342,33 @@ extern "C" fn cli_invoke(
     method: RStr<'_>,
     args: RStr<'_>,
 ) -> RResult<RString, ServiceError> {
+    catch_panics(
+        || dispatch_cli(method, args),
+        ServiceError::invocation_error,
+    )
+}
+
+fn dispatch_cli(method: RStr<'_>, args: RStr<'_>) -> RResult<RString, ServiceError> {
     match method.as_str() {
         "run_command" => {
             let result = run_cli_command(args.as_str());
             match result {
                 Ok(output) => RResult::ROk(RString::from(output)),
-                Err(e) => RResult::RErr(ServiceError::invocation_error(e)),
+                Err(e) if STRUCTURED_ERRORS.load(Ordering::Relaxed) => {
+                    RResult::RErr(ServiceError::invocation_error(e.payload()))
+                }
+                Err(e) => RResult::RErr(ServiceError::invocation_error(e.message)),
             }
         }
         "list_commands" => {
-            let commands = json!([
-                {"name": "list", "description": "List all tasks", "usage": "list [--status <status>] [--ready] [--blocked]"},
-                {"name": "add", "description": "Add a new task", "usage": "add <title> [--description <desc>]"},
-                {"name": "show", "description": "Show task details", "usage": "show <id>"},
-                {"name": "status", "description": "Update task status", "usage": "status <id> <status>"},
-                {"name": "delete", "description": "Delete a task", "usage": "delete <id> [--force]"},
-                {"name": "depend", "description": "Add dependency", "usage": "depend <task-id> <depends-on-id>"},
-                {"name": "undepend", "description": "Remove dependency", "usage": "undepend <task-id> <depends-on-id>"},
-                {"name": "graph", "description": "Show dependency graph", "usage": "graph [--format <text|dot|json>]"},
-                {"name": "search", "description": "Search tasks", "usage": "search <query> [--limit <n>]"},
-                {"name": "blocked", "description": "Show blocked tasks", "usage": "blocked"},
-                {"name": "cycles", "description": "Detect dependency cycles", "usage": "cycles"},
-                {"name": "stats", "description": "Show task statistics", "usage": "stats"}
-            ]);
+            let commands = list_commands_json();
             RResult::ROk(RString::from(
                 serde_json::to_string(&commands).unwrap_or_default(),
             ))
         }
+        "" => RResult::RErr(ServiceError::invocation_error(
+            "Method name is required. Available methods: run_command, list_commands".to_string(),
+        )),
         _ => RResult::RErr(ServiceError::method_not_found(method.as_str())),
     }
 }
@@ -150,14 +382,73 @@ extern "C" fn cli_list_methods(_handle: *const c_void) -> RVec<ServiceMethod> {
     .collect()
 }
 
-fn run_cli_command(context_json: &str) -> Result<String, String> {
-    let context: serde_json::Value =
-        serde_json::from_str(context_json).map_err(|e| format!("Invalid context: {}", e))?;
+/// Every CLI command with its description and usage, shared by list_commands and
+/// the describe_commands message
+fn list_commands_json() -> serde_json::Value {
+    json!([
+        {"name": "list", "description": "List all tasks", "usage": "list [--format <text|json|csv|ids>] [--status <status>[,<status>...]] [--ready] [--blocked] [--limit <n>] [--fields <field>[,<field>...]] [--with-counts] [--group-by root] [--expand dependencies [--max-expanded-deps <n>]]"},
+        {"name": "add", "description": "Add a new task", "usage": "add <title> [--description <desc>] [--depends-on <id>[,<id>...]] [--symbol <symbol-id>] [--status <status> [--force]] [--format <text|pretty>]"},
+        {"name": "bulk-add", "description": "Add several tasks at once", "usage": "bulk-add <title>... [--format <text|json>]"},
+        {"name": "show", "description": "Show task details", "usage": "show <id> [--format <text|json>]"},
+        {"name": "status", "description": "Update task status", "usage": "status <id>[,<id>...] <status> [--format <text|json|pretty>]"},
+        {"name": "reopen", "description": "Reopen a done or cancelled task", "usage": "reopen <id> [<status>]"},
+        {"name": "delete", "description": "Delete a task", "usage": "delete <id> [--force] [--reroute] [--dry-run] [--format <text|json>]"},
+        {"name": "merge", "description": "Merge a duplicate task into another", "usage": "merge <source-id> <target-id> [--force]"},
+        {"name": "clear", "description": "Delete all done tasks", "usage": "clear [--force]"},
+        {"name": "depend", "description": "Add dependency", "usage": "depend <task-id> <depends-on-id>"},
+        {"name": "undepend", "description": "Remove dependency", "usage": "undepend <task-id> <depends-on-id>"},
+        {"name": "redepend", "description": "Move a dependency to another task", "usage": "redepend <task-id> <old-depends-on-id> <new-depends-on-id>"},
+        {"name": "graph", "description": "Show dependency graph", "usage": "graph [--format <text|dot|json|json-graph>]"},
+        {"name": "search", "description": "Search tasks", "usage": "search <query> [--limit <n>] [--status <status>[,<status>...]]"},
+        {"name": "symbol", "description": "Find tasks linked to a code symbol", "usage": "symbol <symbol-id> [--format <text|json>]"},
+        {"name": "blocked", "description": "Show blocked tasks", "usage": "blocked [--format <text|json>]"},
+        {"name": "can-start", "description": "Check whether a task's dependencies are done", "usage": "can-start <id> [--format <text|json>]"},
+        {"name": "cycles", "description": "Detect dependency cycles", "usage": "cycles"},
+        {"name": "plan", "description": "Order open tasks into waves that can run in parallel", "usage": "plan [--format <text|json>]"},
+        {"name": "critical", "description": "Show the longest chain of open dependencies", "usage": "critical [--format <text|json>]"},
+        {"name": "structure", "description": "Show dependency chain depth and fan-out", "usage": "structure [--format <text|json>]"},
+        {"name": "stats", "description": "Show task statistics", "usage": "stats [--format <text|json>]"},
+        {"name": "count", "description": "Count tasks", "usage": "count [--status <status>[,<status>...]] [--format <text|json>]"},
+        {"name": "validate", "description": "Check the task graph for problems", "usage": "validate [--format <text|json>]"},
+        {"name": "autofix", "description": "Repair problems validate can fix safely", "usage": "autofix [--dry-run] [--unblock]"},
+        {"name": "export", "description": "Export tasks", "usage": "export [--format <bundle|json|jsonl|csv|markdown>] [--status <status>[,<status>...]]"},
+        {"name": "import", "description": "Import tasks from a bundle file", "usage": "import <file> [--dry-run]"},
+        {"name": "diff", "description": "Compare two exported snapshots", "usage": "diff <before-file> <after-file> [--format <text|json>]"},
+        {"name": "batch", "description": "Run several commands in one call", "usage": "batch <commands-json>"}
+    ])
+}
+
+/// Option names a command's usage string lists, or `None` for an unknown command
+fn accepted_options(command: &str) -> Option<Vec<String>> {
+    let commands = list_commands_json();
+    let usage = commands.as_array()?.iter().find(|c| c["name"] == command)?["usage"]
+        .as_str()?
+        .to_string();
+    let mut accepted: Vec<String> = usage
+        .split("--")
+        .skip(1)
+        .map(|s| {
+            s.chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+                .collect()
+        })
+        .collect();
+    accepted.dedup();
+    // Global option honoured by every command that prints JSON
+    accepted.push("compact".to_string());
+    Some(accepted)
+}
+
+fn run_cli_command(context_json: &str) -> Result<String, CommandError> {
+    let context: serde_json::Value = serde_json::from_str(context_json)
+        .map_err(|e| CommandError::invalid(format!("Invalid context: {}", e)))?;
 
-    let tasks = TASKS
-        .get()
-        .and_then(|t| t.as_ref())
-        .ok_or_else(|| "Tasks not initialized".to_string())?;
+    let guard = TASKS
+        .read()
+        .map_err(|_| CommandError::unavailable("Tasks lock poisoned"))?;
+    let tasks = guard
+        .as_ref()
+        .ok_or_else(|| CommandError::unavailable(not_initialized()))?;
 
     // Parse command and args from context
     let args: Vec<String> = context
@@ -170,475 +461,4133 @@ fn run_cli_command(context_json: &str) -> Result<String, String> {
         })
         .unwrap_or_default();
 
-    let subcommand = args.first().map(|s| s.as_str()).unwrap_or("");
+    run_args(tasks, &args)
+}
+
+/// Parse one command line (subcommand followed by its arguments) and run it
+fn run_args(tasks: &TaskManager, args: &[String]) -> Result<String, CommandError> {
+    let subcommand = args.first().map(|s| s.trim()).unwrap_or("");
     let cmd_args: Vec<&str> = args.iter().skip(1).map(|s| s.as_str()).collect();
+    let (options, positional) = split_args(&cmd_args);
 
-    // Parse options from remaining args (--key value format)
-    let mut options = serde_json::Map::new();
-    let mut i = 0;
-    while i < cmd_args.len() {
-        if cmd_args[i].starts_with("--") {
-            let key = cmd_args[i].trim_start_matches("--");
-            if i + 1 < cmd_args.len() && !cmd_args[i + 1].starts_with("--") {
-                options.insert(key.to_string(), json!(cmd_args[i + 1]));
-                i += 2;
-            } else {
-                options.insert(key.to_string(), json!(true));
-                i += 1;
+    if STRICT_OPTIONS.load(Ordering::Relaxed) {
+        if let Some(accepted) = accepted_options(subcommand) {
+            let unexpected: Vec<String> = options
+                .keys()
+                .filter(|k| !accepted.contains(k))
+                .map(|k| format!("--{}", k))
+                .collect();
+            if !unexpected.is_empty() {
+                let accepted: Vec<String> = accepted.iter().map(|k| format!("--{}", k)).collect();
+                return Err(CommandError::invalid(format!(
+                    "Unknown options for {}: {} (accepted: {})",
+                    subcommand,
+                    unexpected.join(", "),
+                    if accepted.is_empty() {
+                        "none".to_string()
+                    } else {
+                        accepted.join(", ")
+                    }
+                )));
             }
-        } else {
-            i += 1;
         }
     }
 
-    // Get positional args (non-option args after subcommand)
-    let positional: Vec<&str> = cmd_args
-        .iter()
-        .filter(|a| !a.starts_with("--"))
-        .copied()
-        .collect();
-
     let options_value = serde_json::Value::Object(options);
 
     match subcommand {
         "list" => cmd_list(tasks, &options_value),
         "add" => cmd_add(tasks, &positional, &options_value),
-        "show" => cmd_show(tasks, &positional),
-        "status" => cmd_status(tasks, &positional),
+        "bulk-add" => cmd_bulk_add(tasks, &positional, &options_value),
+        "show" => cmd_show(tasks, &positional, &options_value),
+        "status" => cmd_status(tasks, &positional, &options_value),
+        "reopen" => cmd_reopen(tasks, &positional),
         "delete" => cmd_delete(tasks, &positional, &options_value),
+        "merge" => cmd_merge(tasks, &positional, &options_value),
+        "clear" => cmd_clear_completed(tasks, &options_value),
         "depend" => cmd_depend(tasks, &positional),
         "undepend" => cmd_undepend(tasks, &positional),
+        "redepend" => cmd_redepend(tasks, &positional),
         "graph" => cmd_graph(tasks, &options_value),
         "search" => cmd_search(tasks, &positional, &options_value),
-        "blocked" => cmd_blocked(tasks),
+        "symbol" => cmd_symbol(tasks, &positional, &options_value),
+        "blocked" => cmd_blocked(tasks, &options_value),
+        "can-start" => cmd_can_start(tasks, &positional, &options_value),
         "cycles" => cmd_cycles(tasks),
-        "stats" => cmd_stats(tasks),
+        "plan" => cmd_plan(tasks, &options_value),
+        "critical" => cmd_critical(tasks, &options_value),
+        "structure" => cmd_structure(tasks, &options_value),
+        "stats" => cmd_stats(tasks, &options_value),
+        "count" => cmd_count(tasks, &options_value),
+        "validate" => cmd_validate(tasks, &options_value),
+        "autofix" => cmd_autofix(tasks, &options_value),
+        "export" => cmd_export(tasks, &options_value),
+        "import" => cmd_import(tasks, &positional, &options_value),
+        "diff" => cmd_diff(&positional, &options_value),
+        "batch" => cmd_batch(tasks, &positional, &options_value),
         "" => {
             let help = "ADI Tasks - Task management with dependency tracking\n\n\
                         Commands:\n  \
-                        list     List all tasks\n  \
-                        add      Add a new task\n  \
-                        show     Show task details\n  \
-                        status   Update task status\n  \
-                        delete   Delete a task\n  \
-                        depend   Add dependency\n  \
-                        undepend Remove dependency\n  \
-                        graph    Show dependency graph\n  \
-                        search   Search tasks\n  \
-                        blocked  Show blocked tasks\n  \
-                        cycles   Detect dependency cycles\n  \
-                        stats    Show task statistics\n\n\
+                        list      List all tasks\n  \
+                        add       Add a new task\n  \
+                        bulk-add  Add several tasks at once\n  \
+                        show      Show task details\n  \
+                        status    Update task status\n  \
+                        reopen    Reopen a done or cancelled task\n  \
+                        delete    Delete a task\n  \
+                        merge     Merge a duplicate task into another\n  \
+                        clear     Delete all done tasks\n  \
+                        depend    Add dependency\n  \
+                        undepend  Remove dependency\n  \
+                        redepend  Move a dependency to another task\n  \
+                        graph     Show dependency graph\n  \
+                        search    Search tasks\n  \
+                        symbol    Find tasks linked to a code symbol\n  \
+                        blocked   Show blocked tasks\n  \
+                        can-start Check whether a task can start\n  \
+                        cycles    Detect dependency cycles\n  \
+                        plan      Plan the order of open tasks\n  \
+                        critical  Show the longest chain of open dependencies\n  \
+                        structure Show dependency chain depth and fan-out\n  \
+                        stats     Show task statistics\n  \
+                        count     Count tasks\n  \
+                        validate  Check the task graph for problems\n  \
+                        autofix   Reset blocked tasks whose dependencies are done\n  \
+                        export    Export tasks\n  \
+                        import    Import tasks from a bundle file\n  \
+                        diff      Compare two exported snapshots\n  \
+                        batch     Run several commands in one call\n\n\
+                        Add --compact to print JSON output without indentation.\n\n\
                         Usage: adi run adi.tasks <command> [args]";
             Ok(help.to_string())
         }
-        _ => Err(format!("Unknown command: {}", subcommand)),
+        _ => Err(CommandError::invalid(format!(
+            "Unknown command: {}",
+            subcommand
+        ))),
     }
 }
 
-// === Command Implementations ===
-
-fn cmd_list(tasks: &TaskManager, options: &serde_json::Value) -> Result<String, String> {
-    let status_filter = options.get("status").and_then(|v| v.as_str());
-    let ready = options
-        .get("ready")
-        .and_then(|v| v.as_bool())
-        .unwrap_or(false);
-    let blocked = options
-        .get("blocked")
-        .and_then(|v| v.as_bool())
-        .unwrap_or(false);
-    let format = options
-        .get("format")
-        .and_then(|v| v.as_str())
-        .unwrap_or("text");
+/// Split command arguments into options (`--key value` or bare `--flag`) and positional
+/// args; an option's value is not also treated as a positional arg
+fn split_args<'a>(
+    cmd_args: &[&'a str],
+) -> (serde_json::Map<String, serde_json::Value>, Vec<&'a str>) {
+    let mut options = serde_json::Map::new();
+    let mut positional = Vec::new();
+    let mut i = 0;
+    while i < cmd_args.len() {
+        if cmd_args[i].starts_with("--") {
+            let key = cmd_args[i].trim_start_matches("--");
+            if i + 1 < cmd_args.len() && !cmd_args[i + 1].starts_with("--") {
+                options.insert(key.to_string(), json!(cmd_args[i + 1]));
+                i += 2;
+            } else {
+                options.insert(key.to_string(), json!(true));
+                i += 1;
+            }
+        } else {
+            positional.push(cmd_args[i]);
+            i += 1;
+        }
+    }
+    (options, positional)
+}
 
-    let task_list = if ready {
-        tasks.get_ready().map_err(|e| e.to_string())?
-    } else if blocked {
-        tasks.get_blocked().map_err(|e| e.to_string())?
-    } else if let Some(status_str) = status_filter {
-        let status: TaskStatus = status_str
-            .parse()
-            .map_err(|_| format!("Invalid status: {}", status_str))?;
-        tasks.get_by_status(status).map_err(|e| e.to_string())?
-    } else {
-        tasks.list().map_err(|e| e.to_string())?
-    };
+// === Prompts Service VTable ===
 
-    if format == "json" {
-        return serde_json::to_string_pretty(&task_list).map_err(|e| e.to_string());
-    }
+static PROMPTS_SERVICE_VTABLE: ServiceVTable = ServiceVTable {
+    invoke: prompts_invoke,
+    list_methods: prompts_list_methods,
+};
 
-    if task_list.is_empty() {
-        return Ok("No tasks found".to_string());
-    }
+extern "C" fn prompts_invoke(
+    _handle: *const c_void,
+    method: RStr<'_>,
+    args: RStr<'_>,
+) -> RResult<RString, ServiceError> {
+    catch_panics(
+        || dispatch_prompts(method, args),
+        ServiceError::invocation_error,
+    )
+}
 
-    let mut output = String::new();
-    for task in task_list {
-        let status_icon = match task.status {
-            TaskStatus::Todo => "○",
-            TaskStatus::InProgress => "◐",
-            TaskStatus::Done => "●",
-            TaskStatus::Blocked => "✕",
-            TaskStatus::Cancelled => "○",
-        };
-        let scope = if task.is_global() {
-            "[global]"
-        } else {
-            "[project]"
-        };
-        output.push_str(&format!(
-            "{} #{} {} {}\n",
-            status_icon, task.id.0, task.title, scope
-        ));
+fn dispatch_prompts(method: RStr<'_>, args: RStr<'_>) -> RResult<RString, ServiceError> {
+    match method.as_str() {
+        "list_prompts" => {
+            let prompts = json!([
+                {"name": "standup", "description": "Summarize in-progress, finished and ready work"},
+                {"name": "triage-blocked", "description": "Triage blocked tasks and what they wait on"}
+            ]);
+            RResult::ROk(RString::from(
+                serde_json::to_string(&prompts).unwrap_or_default(),
+            ))
+        }
+        "get_prompt" => match get_prompt(args.as_str()) {
+            Ok(output) => RResult::ROk(RString::from(output)),
+            Err(e) => RResult::RErr(ServiceError::invocation_error(e)),
+        },
+        "" => RResult::RErr(ServiceError::invocation_error(
+            "Method name is required. Available methods: list_prompts, get_prompt".to_string(),
+        )),
+        _ => RResult::RErr(ServiceError::method_not_found(method.as_str())),
     }
-    Ok(output.trim_end().to_string())
 }
 
-fn cmd_add(
-    tasks: &TaskManager,
-    args: &[&str],
-    options: &serde_json::Value,
-) -> Result<String, String> {
-    if args.is_empty() {
-        return Err("Missing title. Usage: add <title> [--description <desc>]".to_string());
-    }
+extern "C" fn prompts_list_methods(_handle: *const c_void) -> RVec<ServiceMethod> {
+    vec![
+        ServiceMethod::new("list_prompts").with_description("List available prompts"),
+        ServiceMethod::new("get_prompt").with_description("Render a prompt by name"),
+    ]
+    .into_iter()
+    .collect()
+}
 
-    let title = args[0];
-    let description = options.get("description").and_then(|v| v.as_str());
-    let depends_on: Vec<i64> = options
-        .get("depends-on")
+/// Render a prompt from `{"name": ...}` as `{"description", "messages"}`
+fn get_prompt(args_json: &str) -> Result<String, String> {
+    let args: serde_json::Value =
+        serde_json::from_str(args_json).map_err(|e| format!("Invalid arguments: {}", e))?;
+    let name = args
+        .get("name")
         .and_then(|v| v.as_str())
-        .map(|s| {
-            s.split(',')
-                .filter_map(|id| id.trim().parse().ok())
-                .collect()
-        })
-        .unwrap_or_default();
+        .ok_or("Missing prompt name")?;
 
-    let mut input = CreateTask::new(title);
-    if let Some(desc) = description {
-        input = input.with_description(desc.to_string());
-    }
-    if !depends_on.is_empty() {
-        input = input.with_dependencies(depends_on.into_iter().map(TaskId).collect());
-    }
+    let guard = TASKS
+        .read()
+        .map_err(|_| "Tasks lock poisoned".to_string())?;
+    let tasks = guard.as_ref().ok_or_else(not_initialized)?;
 
-    let id = tasks.create_task(input).map_err(|e| e.to_string())?;
-    Ok(format!("Created task #{}: {}", id.0, title))
+    let (description, text) = match name {
+        "standup" => (
+            "Summarize in-progress, finished and ready work",
+            standup_prompt(tasks)?,
+        ),
+        "triage-blocked" => (
+            "Triage blocked tasks and what they wait on",
+            format!(
+                "Here are the blocked tasks in this project and what each one is waiting on.\n\n\
+                 {}\n\n\
+                 For each task, suggest how to unblock it: which dependency to finish first, \
+                 or whether the dependency should be dropped.",
+                cmd_blocked(tasks, &json!({}))?
+            ),
+        ),
+        _ => return Err(format!("Unknown prompt: {}", name)),
+    };
+
+    let prompt = json!({
+        "description": description,
+        "messages": [{"role": "user", "content": {"type": "text", "text": text}}]
+    });
+    serde_json::to_string_pretty(&prompt).map_err(|e| e.to_string())
 }
 
-fn cmd_show(tasks: &TaskManager, args: &[&str]) -> Result<String, String> {
-    if args.is_empty() {
-        return Err("Missing task ID. Usage: show <id>".to_string());
-    }
+fn standup_prompt(tasks: &TaskManager) -> Result<String, String> {
+    let section = |heading: &str, list: Vec<Task>| {
+        let mut out = format!("{}:\n", heading);
+        if list.is_empty() {
+            out.push_str("  (none)\n");
+        }
+        for task in list {
+            out.push_str(&format!("  #{} {}\n", task.id.0, task.title));
+        }
+        out
+    };
 
-    let id: i64 = args[0].parse().map_err(|_| "Invalid task ID")?;
-    let task_with_deps = tasks
-        .get_task_with_dependencies(TaskId(id))
-        .map_err(|e| e.to_string())?;
-    let task = &task_with_deps.task;
+    let mut text = String::from(
+        "Write a short standup update from the task list below: what is in progress, \
+         what got done, and what to pick up next.\n\n",
+    );
+    text.push_str(&section(
+        "In progress",
+        tasks
+            .get_by_status(TaskStatus::InProgress)
+            .map_err(|e| e.to_string())?,
+    ));
+    text.push_str(&section(
+        "Done",
+        tasks
+            .get_by_status(TaskStatus::Done)
+            .map_err(|e| e.to_string())?,
+    ));
+    text.push_str(&section(
+        "Ready to start",
+        tasks.get_ready().map_err(|e| e.to_string())?,
+    ));
+    Ok(text.trim_end().to_string())
+}
 
-    let mut output = format!("Task #{}\n", task.id.0);
-    output.push_str(&format!("  Title: {}\n", task.title));
-    output.push_str(&format!("  Status: {:?}\n", task.status));
+// === Helpers ===
 
-    if let Some(ref desc) = task.description {
-        output.push_str(&format!("  Description: {}\n", desc));
+/// Run the body of an `extern "C"` entry point, returning a panic as its error type
+fn catch_panics<T, E>(
+    body: impl FnOnce() -> RResult<T, E>,
+    into_error: impl FnOnce(String) -> E,
+) -> RResult<T, E> {
+    catch_unwind(AssertUnwindSafe(body))
+        .unwrap_or_else(|panic| RResult::RErr(into_error(panic_message(panic))))
+}
+
+/// Describe a panic caught at the FFI boundary, where unwinding further would be UB
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    let detail = panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    format!("Internal error: {}", detail)
+}
+
+/// Error for commands run while no task store is open, with the open failure if known
+fn not_initialized() -> String {
+    match OPEN_ERROR.read().ok().and_then(|e| e.clone()) {
+        Some(reason) => format!("Tasks not initialized: {}", reason),
+        None => "Tasks not initialized".to_string(),
     }
+}
 
-    if let Some(symbol_id) = task.symbol_id {
-        output.push_str(&format!("  Linked symbol: #{}\n", symbol_id));
+fn status_icon(status: TaskStatus) -> &'static str {
+    match status {
+        TaskStatus::Todo => "○",
+        TaskStatus::InProgress => "◐",
+        TaskStatus::Done => "●",
+        TaskStatus::Blocked => "✕",
+        TaskStatus::Cancelled => "○",
     }
+}
 
-    let scope = if task.is_global() {
-        "global"
-    } else {
-        "project"
-    };
-    output.push_str(&format!("  Scope: {}\n", scope));
+/// Parse a status name, ignoring case and `_`/`-`/space separators
+///
+/// `in_progress`, `in-progress`, `InProgress` and `IN PROGRESS` all parse the same.
+/// Names configured with `set_status_aliases` are accepted as well.
+fn parse_status(input: &str) -> Result<TaskStatus, String> {
+    let normalized = normalize_status(input);
+    if let Some(status) = builtin_status(&normalized) {
+        return Ok(status);
+    }
 
-    if !task_with_deps.depends_on.is_empty() {
-        output.push_str("\n  Dependencies:\n");
-        for dep in &task_with_deps.depends_on {
-            output.push_str(&format!("    #{}: {}\n", dep.id.0, dep.title));
-        }
+    let aliases = STATUS_ALIASES
+        .read()
+        .map_err(|_| "Status aliases lock poisoned".to_string())?;
+    if let Some((_, status)) = aliases.iter().find(|(name, _)| *name == normalized) {
+        return Ok(*status);
     }
 
-    if !task_with_deps.dependents.is_empty() {
-        output.push_str("\n  Dependents:\n");
-        for dep in &task_with_deps.dependents {
-            output.push_str(&format!("    #{}: {}\n", dep.id.0, dep.title));
-        }
+    let mut expected = String::from("todo, in_progress, done, blocked or cancelled");
+    if !aliases.is_empty() {
+        let names: Vec<&str> = aliases.iter().map(|(name, _)| name.as_str()).collect();
+        expected.push_str(&format!(", or one of {}", names.join(", ")));
     }
+    Err(format!("Invalid status: {} (expected {})", input, expected))
+}
 
-    Ok(output.trim_end().to_string())
+fn normalize_status(input: &str) -> String {
+    input
+        .chars()
+        .filter(|c| !matches!(c, '_' | '-' | ' '))
+        .flat_map(char::to_lowercase)
+        .collect()
 }
 
-fn cmd_status(tasks: &TaskManager, args: &[&str]) -> Result<String, String> {
-    if args.len() < 2 {
-        return Err("Missing arguments. Usage: status <id> <status>".to_string());
+fn builtin_status(normalized: &str) -> Option<TaskStatus> {
+    match normalized {
+        "todo" => Some(TaskStatus::Todo),
+        "inprogress" => Some(TaskStatus::InProgress),
+        "done" => Some(TaskStatus::Done),
+        "blocked" => Some(TaskStatus::Blocked),
+        "cancelled" | "canceled" => Some(TaskStatus::Cancelled),
+        _ => None,
     }
+}
 
-    let id: i64 = args[0].parse().map_err(|_| "Invalid task ID")?;
-    let status: TaskStatus = args[1]
-        .parse()
-        .map_err(|_| format!("Invalid status: {}", args[1]))?;
+/// Replace the status aliases from a JSON object such as `{"review": "in_progress"}`
+///
+/// Every alias must map onto one of the built-in statuses, which is what gets stored.
+fn set_status_aliases(config: &str) -> Result<(), String> {
+    let config: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(config).map_err(|e| format!("Invalid status aliases: {}", e))?;
 
-    tasks
-        .update_status(TaskId(id), status)
-        .map_err(|e| e.to_string())?;
-    Ok(format!("Task #{} status updated to {:?}", id, status))
+    let mut aliases = Vec::with_capacity(config.len());
+    for (name, target) in &config {
+        let normalized = normalize_status(name);
+        if builtin_status(&normalized).is_some() {
+            return Err(format!("Alias {} shadows a built-in status", name));
+        }
+        let status = target
+            .as_str()
+            .and_then(|t| builtin_status(&normalize_status(t)))
+            .ok_or_else(|| {
+                format!(
+                    "Alias {} must map to a built-in status, got {}",
+                    name, target
+                )
+            })?;
+        aliases.push((normalized, status));
+    }
+
+    *STATUS_ALIASES
+        .write()
+        .map_err(|_| "Status aliases lock poisoned".to_string())? = aliases;
+    Ok(())
 }
 
-fn cmd_delete(
-    tasks: &TaskManager,
-    args: &[&str],
-    options: &serde_json::Value,
-) -> Result<String, String> {
-    if args.is_empty() {
-        return Err("Missing task ID. Usage: delete <id> [--force]".to_string());
+/// Replace the transition rules from a JSON object such as `{"todo": ["in_progress"]}`
+///
+/// An empty object removes all rules.
+fn set_transition_rules(config: &str) -> Result<(), String> {
+    let config: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(config).map_err(|e| format!("Invalid transition rules: {}", e))?;
+
+    let mut rules = Vec::with_capacity(config.len());
+    for (from, targets) in &config {
+        let targets = targets
+            .as_array()
+            .ok_or_else(|| format!("Targets for {} must be a list of statuses", from))?
+            .iter()
+            .map(|t| {
+                t.as_str()
+                    .ok_or_else(|| format!("Invalid status: {}", t))
+                    .and_then(parse_status)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        rules.push((parse_status(from)?, targets));
     }
 
-    let id: i64 = args[0].parse().map_err(|_| "Invalid task ID")?;
-    let force = options
-        .get("force")
-        .and_then(|v| v.as_bool())
-        .unwrap_or(false);
+    *TRANSITION_RULES
+        .write()
+        .map_err(|_| "Transition rules lock poisoned".to_string())? = rules;
+    Ok(())
+}
 
-    let task = tasks.get_task(TaskId(id)).map_err(|e| e.to_string())?;
+/// Check a status change against the configured transition rules
+fn check_transition(from: TaskStatus, to: TaskStatus) -> Result<(), String> {
+    if from == to {
+        return Ok(());
+    }
+    let rules = TRANSITION_RULES
+        .read()
+        .map_err(|_| "Transition rules lock poisoned".to_string())?;
+    match rules.iter().find(|(source, _)| *source == from) {
+        Some((_, allowed)) if !allowed.contains(&to) => {
+            let allowed: Vec<String> = allowed.iter().map(|s| status_label(*s)).collect();
+            Err(format!(
+                "Cannot move from {} to {} (allowed: {})",
+                status_label(from),
+                status_label(to),
+                if allowed.is_empty() {
+                    "none".to_string()
+                } else {
+                    allowed.join(", ")
+                }
+            ))
+        }
+        _ => Ok(()),
+    }
+}
 
-    if !force {
-        return Ok(format!(
-            "Delete task #{}: {}?\nUse --force to confirm deletion",
-            id, task.title
+/// Reject blank titles and titles longer than the configured limit
+fn validate_title(title: &str) -> Result<(), String> {
+    if title.trim().is_empty() {
+        return Err("Title must not be empty".to_string());
+    }
+    let max = MAX_TITLE_LENGTH.load(Ordering::Relaxed);
+    let length = title.chars().count();
+    if length > max {
+        return Err(format!(
+            "Title is {} characters long (maximum is {})",
+            length, max
         ));
     }
+    Ok(())
+}
 
-    tasks.delete_task(TaskId(id)).map_err(|e| e.to_string())?;
-    Ok(format!("Deleted task #{}: {}", id, task.title))
+/// Parse a comma-separated list of statuses, e.g. `todo,in_progress`
+fn parse_status_set(input: &str) -> Result<Vec<TaskStatus>, String> {
+    let statuses = input
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(parse_status)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if statuses.is_empty() {
+        return Err("Missing status".to_string());
+    }
+    Ok(statuses)
 }
 
-fn cmd_depend(tasks: &TaskManager, args: &[&str]) -> Result<String, String> {
-    if args.len() < 2 {
-        return Err("Missing arguments. Usage: depend <task-id> <depends-on-id>".to_string());
+/// Count dependencies and dependents for every task, keyed by task id
+fn dependency_counts(tasks: &TaskManager) -> Result<HashMap<i64, (usize, usize)>, CommandError> {
+    let mut counts: HashMap<i64, (usize, usize)> = HashMap::new();
+    for task in tasks.list().map_err(CommandError::store)? {
+        let deps = tasks
+            .get_dependencies(task.id)
+            .map_err(CommandError::store)?;
+        counts.entry(task.id.0).or_default().0 = deps.len();
+        for dep in deps {
+            counts.entry(dep.id.0).or_default().1 += 1;
+        }
     }
+    Ok(counts)
+}
 
-    let task_id: i64 = args[0].parse().map_err(|_| "Invalid task ID")?;
-    let depends_on: i64 = args[1].parse().map_err(|_| "Invalid depends-on ID")?;
+/// Fetch a task's dependencies, capped at `max` entries
+///
+/// Incomplete dependencies come first since they are the ones still blocking the
+/// task. Returns the retained dependencies and the number left out.
+fn expanded_dependencies(
+    tasks: &TaskManager,
+    id: TaskId,
+    max: Option<usize>,
+) -> Result<(Vec<Task>, usize), CommandError> {
+    let mut deps = tasks.get_dependencies(id).map_err(CommandError::store)?;
+    deps.sort_by_key(|d| d.status.is_complete());
 
-    tasks
-        .add_dependency(TaskId(task_id), TaskId(depends_on))
-        .map_err(|e| e.to_string())?;
-    Ok(format!(
-        "Task #{} now depends on task #{}",
-        task_id, depends_on
-    ))
+    let max = max.unwrap_or(deps.len());
+    let more = deps.len().saturating_sub(max);
+    deps.truncate(max);
+    Ok((deps, more))
 }
 
-fn cmd_undepend(tasks: &TaskManager, args: &[&str]) -> Result<String, String> {
-    if args.len() < 2 {
-        return Err("Missing arguments. Usage: undepend <task-id> <depends-on-id>".to_string());
+/// Find a path from `from` to `to` following "depends on" edges
+///
+/// The returned path includes both ends. Adding the edge `to -> from` would close a
+/// cycle exactly when such a path exists.
+fn graph_path(deps: &HashMap<i64, Vec<i64>>, from: i64, to: i64) -> Option<Vec<i64>> {
+    let mut parents: HashMap<i64, i64> = HashMap::new();
+    let mut visited = HashSet::from([from]);
+    let mut stack = vec![from];
+
+    while let Some(current) = stack.pop() {
+        if current == to {
+            let mut path = vec![current];
+            let mut id = current;
+            while let Some(&parent) = parents.get(&id) {
+                path.push(parent);
+                id = parent;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for &dep in deps.get(&current).into_iter().flatten() {
+            if visited.insert(dep) {
+                parents.insert(dep, current);
+                stack.push(dep);
+            }
+        }
     }
+    None
+}
 
-    let task_id: i64 = args[0].parse().map_err(|_| "Invalid task ID")?;
-    let depends_on: i64 = args[1].parse().map_err(|_| "Invalid depends-on ID")?;
+/// Run a store write, retrying with backoff while the store reports it is locked
+///
+/// Only lock/busy errors are retried; anything else is returned immediately. Once the
+/// retries are used up the error is marked retryable so the caller can try again later.
+fn with_retry<T, E: std::fmt::Display>(
+    mut op: impl FnMut() -> Result<T, E>,
+) -> Result<T, CommandError> {
+    let retries = WRITE_RETRIES.load(Ordering::Relaxed);
+    let mut delay = Duration::from_millis(20);
+    let mut attempt = 0;
+    loop {
+        let err = match op() {
+            Ok(value) => return Ok(value),
+            Err(e) => e.to_string(),
+        };
+        if !is_lock_error(&err) {
+            return Err(CommandError::store(err));
+        }
+        if attempt >= retries {
+            let mut error = CommandError::unavailable(format!("{} (retryable)", err));
+            error.retryable = true;
+            return Err(error);
+        }
+        std::thread::sleep(delay);
+        delay *= 2;
+        attempt += 1;
+    }
+}
 
-    tasks
-        .remove_dependency(TaskId(task_id), TaskId(depends_on))
-        .map_err(|e| e.to_string())?;
-    Ok(format!(
-        "Removed dependency: #{} -> #{}",
-        task_id, depends_on
-    ))
+/// Whether a store error is the transient "database is locked" / "busy" kind
+///
+/// Matches whole words so errors that merely mention a blocked task aren't retried.
+fn is_lock_error(message: &str) -> bool {
+    message
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| word.eq_ignore_ascii_case("locked") || word.eq_ignore_ascii_case("busy"))
 }
 
-fn cmd_graph(tasks: &TaskManager, options: &serde_json::Value) -> Result<String, String> {
-    let format = options
-        .get("format")
-        .and_then(|v| v.as_str())
-        .unwrap_or("text");
-    let all_tasks = tasks.list().map_err(|e| e.to_string())?;
+/// Stable category of a command failure, reported as `code` in structured errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorCode {
+    NotFound,
+    InvalidArgument,
+    CycleDetected,
+    StoreUnavailable,
+    Internal,
+}
 
-    if format == "json" {
-        let mut graph_data = Vec::new();
-        for task in &all_tasks {
-            let deps = tasks.get_dependencies(task.id).map_err(|e| e.to_string())?;
-            graph_data.push(json!({
-                "task": task,
-                "dependencies": deps.iter().map(|d| d.id.0).collect::<Vec<_>>()
-            }));
+impl ErrorCode {
+    fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::InvalidArgument => "invalid_argument",
+            ErrorCode::CycleDetected => "cycle_detected",
+            ErrorCode::StoreUnavailable => "store_unavailable",
+            ErrorCode::Internal => "internal",
         }
-        return serde_json::to_string_pretty(&graph_data).map_err(|e| e.to_string());
     }
+}
 
-    if format == "dot" {
-        let mut output = String::from("digraph tasks {\n  rankdir=LR;\n");
-        for task in &all_tasks {
-            let label = task.title.replace('"', "\\\"");
-            let color = match task.status {
-                TaskStatus::Done => "green",
-                TaskStatus::InProgress => "blue",
-                TaskStatus::Blocked => "red",
-                TaskStatus::Cancelled => "gray",
-                TaskStatus::Todo => "black",
-            };
-            output.push_str(&format!(
-                "  {} [label=\"{}\" color=\"{}\"];\n",
-                task.id.0, label, color
-            ));
+/// A command failure: the message shown to the user plus the code it was raised with
+///
+/// Plain string errors, such as those from `parse_status` or `validate_title`, convert
+/// to `InvalidArgument`; every other code is chosen where the error is raised.
+#[derive(Debug)]
+struct CommandError {
+    code: ErrorCode,
+    message: String,
+    /// Set when the store stayed locked through every retry
+    retryable: bool,
+}
 
-            let deps = tasks.get_dependencies(task.id).map_err(|e| e.to_string())?;
-            for dep in deps {
-                output.push_str(&format!("  {} -> {};\n", task.id.0, dep.id.0));
-            }
+impl CommandError {
+    fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        CommandError {
+            code,
+            message: message.into(),
+            retryable: false,
         }
-        output.push_str("}\n");
-        return Ok(output);
     }
 
-    // Text format
-    if all_tasks.is_empty() {
-        return Ok("No tasks found".to_string());
+    fn invalid(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidArgument, message)
     }
 
-    let mut output = String::from("Task Dependency Graph\n\n");
-    for task in &all_tasks {
-        let status_icon = match task.status {
-            TaskStatus::Todo => "○",
-            TaskStatus::InProgress => "◐",
-            TaskStatus::Done => "●",
-            TaskStatus::Blocked => "✕",
-            TaskStatus::Cancelled => "○",
+    fn not_found(id: i64) -> Self {
+        Self::new(ErrorCode::NotFound, format!("Task #{} not found", id))
+    }
+
+    fn cycle(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::CycleDetected, message)
+    }
+
+    fn unavailable(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::StoreUnavailable, message)
+    }
+
+    /// An error reported by the task store
+    fn store(error: impl std::fmt::Display) -> Self {
+        let message = error.to_string();
+        if is_lock_error(&message) {
+            Self::unavailable(message)
+        } else {
+            Self::new(ErrorCode::Internal, message)
+        }
+    }
+
+    /// A failure in the plugin itself, such as serialization
+    fn internal(error: impl std::fmt::Display) -> Self {
+        Self::new(ErrorCode::Internal, error.to_string())
+    }
+
+    /// Wrap as `{"isError": true, "error": {code, message, details}}`
+    fn payload(&self) -> String {
+        let details = if self.retryable {
+            json!({ "retryable": true })
+        } else {
+            serde_json::Value::Null
         };
-        output.push_str(&format!("{} #{} {}\n", status_icon, task.id.0, task.title));
+        json!({
+            "isError": true,
+            "error": {
+                "code": self.code.as_str(),
+                "message": self.message,
+                "details": details,
+            }
+        })
+        .to_string()
+    }
+}
 
-        let deps = tasks.get_dependencies(task.id).map_err(|e| e.to_string())?;
-        for (i, dep) in deps.iter().enumerate() {
-            let prefix = if i == deps.len() - 1 {
-                "  └─"
-            } else {
-                "  ├─"
-            };
-            output.push_str(&format!(
-                "{} depends on #{}: {}\n",
-                prefix, dep.id.0, dep.title
-            ));
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        Self::invalid(message)
+    }
+}
+
+impl From<&str> for CommandError {
+    fn from(message: &str) -> Self {
+        Self::invalid(message)
+    }
+}
+
+impl From<CommandError> for String {
+    fn from(error: CommandError) -> Self {
+        error.message
+    }
+}
+
+/// Serialize command output, pretty-printed unless `--compact` was passed
+///
+/// When the output exceeds the configured size limit and is a list, the list is cut
+/// down to fit and wrapped as `{"truncated", "returned", "total", "items"}`.
+fn to_json<T: serde::Serialize + ?Sized>(
+    value: &T,
+    options: &serde_json::Value,
+) -> Result<String, CommandError> {
+    let output = to_json_unlimited(value, options)?;
+    let max = MAX_OUTPUT_BYTES.load(Ordering::Relaxed);
+    if max == 0 || output.len() <= max {
+        return Ok(output);
+    }
+    let value = serde_json::to_value(value).map_err(CommandError::internal)?;
+    let Some(items) = value.as_array() else {
+        return Ok(output);
+    };
+
+    let truncated = |returned: usize| {
+        to_json_unlimited(
+            &json!({
+                "truncated": true,
+                "returned": returned,
+                "total": items.len(),
+                "items": &items[..returned]
+            }),
+            options,
+        )
+    };
+    // Binary search for the longest prefix that still fits
+    let (mut low, mut high) = (0, items.len());
+    while low < high {
+        let mid = (low + high + 1) / 2;
+        if truncated(mid)?.len() <= max {
+            low = mid;
+        } else {
+            high = mid - 1;
         }
     }
-    Ok(output.trim_end().to_string())
+    truncated(low)
 }
 
-fn cmd_search(
-    tasks: &TaskManager,
-    args: &[&str],
+/// Like `to_json` but never truncates, for output that must not lose entries
+fn to_json_unlimited<T: serde::Serialize + ?Sized>(
+    value: &T,
     options: &serde_json::Value,
-) -> Result<String, String> {
-    if args.is_empty() {
-        return Err("Missing query. Usage: search <query> [--limit <n>]".to_string());
+) -> Result<String, CommandError> {
+    let compact = options
+        .get("compact")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    if compact {
+        serde_json::to_string(value).map_err(CommandError::internal)
+    } else {
+        serde_json::to_string_pretty(value).map_err(CommandError::internal)
     }
+}
 
-    let query = args[0];
-    let limit = options
-        .get("limit")
-        .and_then(|v| v.as_str())
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(10usize);
+/// Status as it appears in serialized output, e.g. `in_progress`
+fn status_label(status: TaskStatus) -> String {
+    serde_json::to_value(status)
+        .ok()
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or_else(|| format!("{:?}", status))
+}
 
-    let results = tasks.search(query, limit).map_err(|e| e.to_string())?;
+/// Render tasks as CSV with an `id,title,status,description` header
+fn render_csv(task_list: &[Task]) -> String {
+    let mut output = String::from("id,title,status,description\n");
+    for task in task_list {
+        output.push_str(&format!(
+            "{},{},{},{}\n",
+            task.id.0,
+            csv_field(&task.title),
+            status_label(task.status),
+            csv_field(task.description.as_deref().unwrap_or(""))
+        ));
+    }
+    output
+}
 
-    if results.is_empty() {
-        return Ok("No tasks found".to_string());
+/// Quote a CSV field when it contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
     }
+}
 
-    let mut output = format!("Found {} results for \"{}\":\n\n", results.len(), query);
-    for task in results {
-        let status_icon = match task.status {
-            TaskStatus::Todo => "○",
-            TaskStatus::InProgress => "◐",
-            TaskStatus::Done => "●",
-            TaskStatus::Blocked => "✕",
-            TaskStatus::Cancelled => "○",
-        };
-        output.push_str(&format!("{} #{} {}\n", status_icon, task.id.0, task.title));
+/// Render tasks as a Markdown table with id, title, status and description columns
+fn render_markdown(task_list: &[Task]) -> String {
+    let mut output = String::from("| ID | Title | Status | Description |\n");
+    output.push_str("|---:|---|---|---|\n");
+    for task in task_list {
+        output.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            task.id.0,
+            markdown_cell(&task.title),
+            status_label(task.status),
+            markdown_cell(task.description.as_deref().unwrap_or(""))
+        ));
     }
-    Ok(output.trim_end().to_string())
+    output
 }
 
-fn cmd_blocked(tasks: &TaskManager) -> Result<String, String> {
-    let blocked = tasks.get_blocked().map_err(|e| e.to_string())?;
+/// Escape pipes and turn line breaks into `<br>` so text stays inside one table cell
+fn markdown_cell(value: &str) -> String {
+    value
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace(['\n', '\r'], "<br>")
+}
 
-    if blocked.is_empty() {
-        return Ok("No blocked tasks".to_string());
+/// Escape text for use inside a double-quoted DOT string
+fn dot_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
     }
+    escaped
+}
 
-    let mut output = String::from("Blocked Tasks\n\n");
-    for task in blocked {
-        output.push_str(&format!("✕ #{} {}\n", task.id.0, task.title));
+/// Load every task together with its dependency ids, keyed by task id
+fn load_graph(tasks: &TaskManager) -> Result<(Vec<Task>, HashMap<i64, Vec<i64>>), CommandError> {
+    let all_tasks = tasks.list().map_err(CommandError::store)?;
+    let mut deps = HashMap::with_capacity(all_tasks.len());
+    for task in &all_tasks {
+        let task_deps = tasks
+            .get_dependencies(task.id)
+            .map_err(CommandError::store)?;
+        deps.insert(task.id.0, task_deps.iter().map(|d| d.id.0).collect());
+    }
+    Ok((all_tasks, deps))
+}
 
-        let blockers = tasks.get_dependencies(task.id).map_err(|e| e.to_string())?;
-        let incomplete_blockers: Vec<_> = blockers
-            .iter()
-            .filter(|t| !t.status.is_complete())
-            .collect();
+/// Restrict a graph to open tasks, dropping edges to completed ones
+///
+/// Returns the open tasks by id and the dependencies between them.
+fn open_graph<'a>(
+    all_tasks: &'a [Task],
+    deps: &HashMap<i64, Vec<i64>>,
+) -> (HashMap<i64, &'a Task>, HashMap<i64, Vec<i64>>) {
+    let open: HashMap<i64, &Task> = all_tasks
+        .iter()
+        .filter(|t| !t.status.is_complete())
+        .map(|t| (t.id.0, t))
+        .collect();
+    let open_deps: HashMap<i64, Vec<i64>> = open
+        .keys()
+        .map(|id| {
+            let task_deps = deps.get(id).into_iter().flatten();
+            (
+                *id,
+                task_deps
+                    .filter(|d| open.contains_key(*d))
+                    .copied()
+                    .collect(),
+            )
+        })
+        .collect();
+    (open, open_deps)
+}
 
-        for blocker in incomplete_blockers {
-            output.push_str(&format!(
-                "  └─ blocked by #{}: {} ({:?})\n",
-                blocker.id.0, blocker.title, blocker.status
-            ));
+/// Map each task id to the roots that ultimately depend on it
+///
+/// A root is a task nothing else depends on, i.e. the deliverable a chain of work
+/// serves. Roots map to themselves; tasks only reachable through a cycle get no entry.
+fn task_roots(
+    ids: impl IntoIterator<Item = i64>,
+    deps: &HashMap<i64, Vec<i64>>,
+) -> HashMap<i64, Vec<i64>> {
+    let depended_on: HashSet<i64> = deps.values().flatten().copied().collect();
+
+    let mut roots_of: HashMap<i64, Vec<i64>> = HashMap::new();
+    for root in ids.into_iter().filter(|id| !depended_on.contains(id)) {
+        let mut visited = HashSet::from([root]);
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            roots_of.entry(id).or_default().push(root);
+            for dep in deps.get(&id).into_iter().flatten() {
+                if visited.insert(*dep) {
+                    stack.push(*dep);
+                }
+            }
         }
     }
-    Ok(output.trim_end().to_string())
+    roots_of
 }
 
-fn cmd_cycles(tasks: &TaskManager) -> Result<String, String> {
-    let cycles = tasks.detect_cycles().map_err(|e| e.to_string())?;
+/// Find a cycle in an in-memory dependency graph
+///
+/// Returns the ids along the cycle in dependency order, without repeating the first.
+fn find_cycle(deps: &HashMap<i64, Vec<i64>>) -> Option<Vec<i64>> {
+    enum Mark {
+        Visiting,
+        Done,
+    }
 
-    if cycles.is_empty() {
-        return Ok("No circular dependencies detected".to_string());
+    fn visit(
+        id: i64,
+        deps: &HashMap<i64, Vec<i64>>,
+        marks: &mut HashMap<i64, Mark>,
+        stack: &mut Vec<i64>,
+    ) -> Option<Vec<i64>> {
+        match marks.get(&id) {
+            Some(Mark::Done) => return None,
+            Some(Mark::Visiting) => {
+                let start = stack.iter().position(|&s| s == id).unwrap_or(0);
+                return Some(stack[start..].to_vec());
+            }
+            None => {}
+        }
+
+        marks.insert(id, Mark::Visiting);
+        stack.push(id);
+        for &dep in deps.get(&id).into_iter().flatten() {
+            if let Some(cycle) = visit(dep, deps, marks, stack) {
+                return Some(cycle);
+            }
+        }
+        stack.pop();
+        marks.insert(id, Mark::Done);
+        None
     }
 
-    let mut output = format!("Found {} circular dependencies:\n\n", cycles.len());
-    for (i, cycle) in cycles.iter().enumerate() {
-        output.push_str(&format!("  Cycle {}: ", i + 1));
-        let cycle_str = cycle
-            .iter()
-            .map(|id| format!("#{}", id.0))
-            .collect::<Vec<_>>()
-            .join(" -> ");
-        output.push_str(&format!(
-            "{} -> #{}\n",
-            cycle_str,
-            cycle.first().map(|id| id.0).unwrap_or(0)
-        ));
+    let mut ids: Vec<i64> = deps.keys().copied().collect();
+    ids.sort_unstable();
+
+    let mut marks = HashMap::new();
+    for id in ids {
+        let mut stack = Vec::new();
+        if let Some(cycle) = visit(id, deps, &mut marks, &mut stack) {
+            return Some(cycle);
+        }
     }
-    Ok(output.trim_end().to_string())
+    None
 }
 
-fn cmd_stats(tasks: &TaskManager) -> Result<String, String> {
-    let status = tasks.status().map_err(|e| e.to_string())?;
+/// Length of the longest dependency chain ending at each task, with the dependency it
+/// continues through
+///
+/// The graph must be acyclic. Edges to ids outside `deps` are ignored; ties go to the
+/// lowest dependency id so chains are stable.
+fn chain_depths(deps: &HashMap<i64, Vec<i64>>) -> HashMap<i64, (usize, Option<i64>)> {
+    fn visit(
+        id: i64,
+        deps: &HashMap<i64, Vec<i64>>,
+        memo: &mut HashMap<i64, (usize, Option<i64>)>,
+    ) -> usize {
+        if let Some((len, _)) = memo.get(&id) {
+            return *len;
+        }
+        let mut best: (usize, Option<i64>) = (0, None);
+        let mut task_deps: Vec<i64> = deps
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|d| deps.contains_key(d))
+            .collect();
+        task_deps.sort_unstable();
+        for dep in task_deps {
+            let len = visit(dep, deps, memo);
+            if len > best.0 {
+                best = (len, Some(dep));
+            }
+        }
+        memo.insert(id, (best.0 + 1, best.1));
+        best.0 + 1
+    }
+
+    let mut memo = HashMap::with_capacity(deps.len());
+    for id in deps.keys() {
+        visit(*id, deps, &mut memo);
+    }
+    memo
+}
 
-    let mut output = String::from("Task Statistics\n\n");
-    output.push_str(&format!("  Total tasks:     {}\n", status.total_tasks));
-    output.push_str(&format!("  Todo:            {}\n", status.todo_count));
-    output.push_str(&format!(
-        "  In Progress:     {}\n",
-        status.in_progress_count
-    ));
-    output.push_str(&format!("  Done:            {}\n", status.done_count));
-    output.push_str(&format!("  Blocked:         {}\n", status.blocked_count));
-    output.push_str(&format!("  Cancelled:       {}\n", status.cancelled_count));
-    output.push_str(&format!(
-        "\n  Dependencies:    {}\n",
-        status.total_dependencies
-    ));
+/// Group tasks into waves whose dependencies all sit in earlier waves
+///
+/// Every task in a wave can run in parallel; ids within a wave are sorted. Edges to ids
+/// outside `deps` are ignored. Returns the cycle instead when the graph has one.
+fn plan_waves(deps: &HashMap<i64, Vec<i64>>) -> Result<Vec<Vec<i64>>, Vec<i64>> {
+    if let Some(cycle) = find_cycle(deps) {
+        return Err(cycle);
+    }
 
-    if status.has_cycles {
-        output.push_str("  Cycles:          Yes (run 'cycles' to see)\n");
-    } else {
-        output.push_str("  Cycles:          None\n");
+    // Kahn's algorithm, emitting every task whose dependencies are all planned as one wave
+    let mut pending: HashMap<i64, usize> = HashMap::with_capacity(deps.len());
+    let mut dependents: HashMap<i64, Vec<i64>> = HashMap::new();
+    for (id, task_deps) in deps {
+        let task_deps: Vec<i64> = task_deps
+            .iter()
+            .copied()
+            .filter(|d| deps.contains_key(d))
+            .collect();
+        pending.insert(*id, task_deps.len());
+        for dep in task_deps {
+            dependents.entry(dep).or_default().push(*id);
+        }
+    }
+    let mut waves: Vec<Vec<i64>> = Vec::new();
+    let mut wave: Vec<i64> = pending
+        .iter()
+        .filter(|(_, n)| **n == 0)
+        .map(|(id, _)| *id)
+        .collect();
+    while !wave.is_empty() {
+        wave.sort_unstable();
+        let mut next = Vec::new();
+        for id in &wave {
+            for dependent in dependents.get(id).into_iter().flatten() {
+                if let Some(n) = pending.get_mut(dependent) {
+                    *n -= 1;
+                    if *n == 0 {
+                        next.push(*dependent);
+                    }
+                }
+            }
+        }
+        waves.push(wave);
+        wave = next;
     }
+    Ok(waves)
+}
 
-    Ok(output.trim_end().to_string())
+/// Render a cycle as `#1 -> #2 -> #1`
+fn format_cycle(cycle: &[i64]) -> String {
+    cycle
+        .iter()
+        .chain(cycle.first())
+        .map(|id| format!("#{}", id))
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// The cycle that adding the edge `task -> depends_on` would close, if any
+///
+/// Ids come in dependency order starting at `task`, as `format_cycle` expects.
+fn closed_cycle(deps: &HashMap<i64, Vec<i64>>, task: i64, depends_on: i64) -> Option<Vec<i64>> {
+    let path = graph_path(deps, depends_on, task)?;
+    Some(
+        std::iter::once(task)
+            .chain(path[..path.len() - 1].iter().copied())
+            .collect(),
+    )
+}
+
+// === Command Implementations ===
+
+fn cmd_list(tasks: &TaskManager, options: &serde_json::Value) -> Result<String, CommandError> {
+    let status_filter = options.get("status").and_then(|v| v.as_str());
+    let ready = options
+        .get("ready")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let blocked = options
+        .get("blocked")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let with_counts = options
+        .get("with-counts")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let expand_deps = match options.get("expand").and_then(|v| v.as_str()) {
+        Some("dependencies") => true,
+        Some(other) => {
+            return Err(CommandError::invalid(format!(
+                "Unknown expansion: {}",
+                other
+            )))
+        }
+        None => false,
+    };
+    let max_expanded_deps = options
+        .get("max-expanded-deps")
+        .and_then(|v| v.as_str())
+        .map(|s| {
+            s.parse::<usize>()
+                .map_err(|_| format!("Invalid max-expanded-deps: {}", s))
+        })
+        .transpose()?;
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("text");
+    // `id` is always kept so projected entries stay addressable
+    let fields: Option<HashSet<&str>> = options
+        .get("fields")
+        .and_then(|v| v.as_str())
+        .map(|s| s.split(',').map(str::trim).chain(["id"]).collect());
+
+    let limit = options
+        .get("limit")
+        .and_then(|v| v.as_str())
+        .map(|s| {
+            s.parse::<usize>()
+                .map_err(|_| format!("Invalid limit: {}", s))
+        })
+        .transpose()?;
+
+    let mut task_list = if ready {
+        tasks.get_ready().map_err(CommandError::store)?
+    } else if blocked {
+        let (all_tasks, deps) = load_graph(tasks)?;
+        let blocked_ids: HashSet<i64> = blocked_tasks(&all_tasks, &deps)
+            .iter()
+            .map(|(t, _)| t.id.0)
+            .collect();
+        all_tasks
+            .into_iter()
+            .filter(|t| blocked_ids.contains(&t.id.0))
+            .collect()
+    } else if let Some(status_str) = status_filter {
+        let statuses = parse_status_set(status_str)?;
+        if let [status] = statuses.as_slice() {
+            tasks.get_by_status(*status).map_err(CommandError::store)?
+        } else {
+            tasks
+                .list()
+                .map_err(CommandError::store)?
+                .into_iter()
+                .filter(|t| statuses.contains(&t.status))
+                .collect()
+        }
+    } else {
+        tasks.list().map_err(CommandError::store)?
+    };
+    // Store order depends on the backend; sort so output and --limit are stable
+    task_list.sort_by_key(|t| t.id.0);
+    if let Some(limit) = limit {
+        task_list.truncate(limit);
+    }
+
+    match options.get("group-by").and_then(|v| v.as_str()) {
+        Some("root") => return list_grouped_by_root(tasks, &task_list, format, options),
+        Some(other) => {
+            return Err(CommandError::invalid(format!(
+                "Unknown grouping: {}",
+                other
+            )))
+        }
+        None => {}
+    }
+
+    if format == "csv" {
+        return Ok(render_csv(&task_list));
+    }
+
+    if format == "ids" {
+        let ids: Vec<i64> = task_list.iter().map(|t| t.id.0).collect();
+        return to_json(&ids, options);
+    }
+
+    let counts = if with_counts {
+        Some(dependency_counts(tasks)?)
+    } else {
+        None
+    };
+
+    if format == "json" {
+        if counts.is_none() && !expand_deps && fields.is_none() {
+            return to_json(&task_list, options);
+        }
+        let mut items = Vec::with_capacity(task_list.len());
+        for task in &task_list {
+            let mut value = serde_json::to_value(task).map_err(CommandError::internal)?;
+            if let Some(obj) = value.as_object_mut() {
+                if let Some(ref counts) = counts {
+                    let (depends_on_count, dependent_count) =
+                        counts.get(&task.id.0).copied().unwrap_or_default();
+                    obj.insert("depends_on_count".to_string(), json!(depends_on_count));
+                    obj.insert("dependent_count".to_string(), json!(dependent_count));
+                }
+                if expand_deps {
+                    let (deps, more) = expanded_dependencies(tasks, task.id, max_expanded_deps)?;
+                    obj.insert("dependencies".to_string(), json!(deps));
+                    if more > 0 {
+                        obj.insert("more_deps".to_string(), json!(more));
+                    }
+                }
+                if let Some(ref fields) = fields {
+                    obj.retain(|key, _| fields.contains(key.as_str()));
+                }
+            }
+            items.push(value);
+        }
+        return to_json(&items, options);
+    }
+
+    if task_list.is_empty() {
+        return Ok("No tasks found".to_string());
+    }
+
+    let mut output = String::new();
+    for task in task_list {
+        let status_icon = status_icon(task.status);
+        let scope = if task.is_global() {
+            "[global]"
+        } else {
+            "[project]"
+        };
+        output.push_str(&format!(
+            "{} #{} {} {}",
+            status_icon, task.id.0, task.title, scope
+        ));
+        if let Some(ref counts) = counts {
+            let (depends_on_count, dependent_count) =
+                counts.get(&task.id.0).copied().unwrap_or_default();
+            output.push_str(&format!(
+                " (deps: {}, dependents: {})",
+                depends_on_count, dependent_count
+            ));
+        }
+        output.push('\n');
+
+        if expand_deps {
+            let (deps, more) = expanded_dependencies(tasks, task.id, max_expanded_deps)?;
+            for (i, dep) in deps.iter().enumerate() {
+                let prefix = if i == deps.len() - 1 && more == 0 {
+                    "  └─"
+                } else {
+                    "  ├─"
+                };
+                output.push_str(&format!("{} #{}: {}\n", prefix, dep.id.0, dep.title));
+            }
+            if more > 0 {
+                output.push_str(&format!("  └─ ... and {} more\n", more));
+            }
+        }
+    }
+    Ok(output.trim_end().to_string())
+}
+
+/// Render `task_list` bucketed under the root task(s) each entry serves
+fn list_grouped_by_root(
+    tasks: &TaskManager,
+    task_list: &[Task],
+    format: &str,
+    options: &serde_json::Value,
+) -> Result<String, CommandError> {
+    let (all_tasks, deps) = load_graph(tasks)?;
+    let roots_of = task_roots(all_tasks.iter().map(|t| t.id.0), &deps);
+
+    // Groups keep the order roots appear in the store; tasks without a root go last
+    let mut groups: Vec<(Option<&Task>, Vec<&Task>)> = all_tasks
+        .iter()
+        .filter(|t| roots_of.get(&t.id.0).map(|r| r.contains(&t.id.0)) == Some(true))
+        .map(|root| (Some(root), Vec::new()))
+        .collect();
+    let mut unrooted = Vec::new();
+    for task in task_list {
+        match roots_of.get(&task.id.0) {
+            Some(roots) => {
+                for (root, members) in groups.iter_mut() {
+                    if root.is_some_and(|r| roots.contains(&r.id.0)) {
+                        members.push(task);
+                    }
+                }
+            }
+            None => unrooted.push(task),
+        }
+    }
+    groups.retain(|(_, members)| !members.is_empty());
+    if !unrooted.is_empty() {
+        groups.push((None, unrooted));
+    }
+
+    let shared = |task: &Task| roots_of.get(&task.id.0).map_or(0, |r| r.len()) > 1;
+
+    if format == "json" {
+        let groups: Vec<_> = groups
+            .iter()
+            .map(|(root, members)| {
+                json!({
+                    "root_id": root.map(|r| r.id.0),
+                    "root_title": root.map(|r| r.title.as_str()),
+                    "tasks": members.iter().map(|&t| {
+                        let mut value = serde_json::to_value(t).unwrap_or_default();
+                        if let Some(obj) = value.as_object_mut() {
+                            obj.insert("shared".to_string(), json!(shared(t)));
+                        }
+                        value
+                    }).collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        return to_json(&groups, options);
+    }
+
+    if groups.is_empty() {
+        return Ok("No tasks found".to_string());
+    }
+
+    let mut output = String::new();
+    for (root, members) in &groups {
+        match root {
+            Some(root) => output.push_str(&format!("#{} {}\n", root.id.0, root.title)),
+            None => output.push_str("(no root)\n"),
+        }
+        for &task in members {
+            let marker = if shared(task) { " (shared)" } else { "" };
+            output.push_str(&format!(
+                "  {} #{} {}{}\n",
+                status_icon(task.status),
+                task.id.0,
+                task.title,
+                marker
+            ));
+        }
+        output.push('\n');
+    }
+    Ok(output.trim_end().to_string())
+}
+
+fn cmd_add(
+    tasks: &TaskManager,
+    args: &[&str],
+    options: &serde_json::Value,
+) -> Result<String, CommandError> {
+    if args.is_empty() {
+        return Err(CommandError::invalid(
+            "Missing title. Usage: add <title> [--description <desc>] [--depends-on <ids>] \
+             [--symbol <symbol-id>]",
+        ));
+    }
+
+    let title = args[0];
+    validate_title(title)?;
+    let description = options.get("description").and_then(|v| v.as_str());
+    let depends_on: Vec<i64> = options
+        .get("depends-on")
+        .and_then(|v| v.as_str())
+        .map(|s| {
+            s.split(',')
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .map(|id| {
+                    id.parse()
+                        .map_err(|_| format!("Invalid depends-on ID: {}", id))
+                })
+                .collect::<Result<_, _>>()
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    let status = options
+        .get("status")
+        .and_then(|v| v.as_str())
+        .map(parse_status)
+        .transpose()?
+        .unwrap_or(TaskStatus::Todo);
+    let force = options
+        .get("force")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    if status == TaskStatus::Done && !force {
+        return Err(CommandError::invalid(
+            "Creating a task that is already done needs --force",
+        ));
+    }
+
+    let symbol_id: Option<i64> = options
+        .get("symbol")
+        .and_then(|v| v.as_str())
+        .map(|s| {
+            s.trim()
+                .parse()
+                .map_err(|_| format!("Invalid symbol ID: {}", s))
+        })
+        .transpose()?;
+
+    let unknown: Vec<String> = depends_on
+        .iter()
+        .filter(|id| tasks.get_task(TaskId(**id)).is_err())
+        .map(|id| format!("#{}", id))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(CommandError::invalid(format!(
+            "Unknown dependencies: {}",
+            unknown.join(", ")
+        )));
+    }
+
+    let id = with_retry(|| {
+        let mut input = CreateTask::new(title);
+        if let Some(desc) = description {
+            input = input.with_description(desc.to_string());
+        }
+        if !depends_on.is_empty() {
+            input = input.with_dependencies(depends_on.iter().copied().map(TaskId).collect());
+        }
+        input.symbol_id = symbol_id;
+        tasks.create_task(input)
+    })?;
+    // The task exists by now, so a failure names it; retrying the add would duplicate it
+    if status != TaskStatus::Todo {
+        with_retry(|| tasks.update_status(id, status)).map_err(|mut e| {
+            e.message = format!(
+                "Created task #{} but could not set its status to {}: {}",
+                id.0,
+                status_label(status),
+                e.message
+            );
+            e.retryable = false;
+            e
+        })?;
+    }
+
+    let confirmation = format!("Created task #{}: {}", id.0, title);
+    if options.get("format").and_then(|v| v.as_str()) == Some("pretty") {
+        let details = cmd_show(tasks, &[id.0.to_string().as_str()], &json!({}))?;
+        return Ok(format!("{}\n\n{}", confirmation, details));
+    }
+    Ok(confirmation)
+}
+
+fn cmd_bulk_add(
+    tasks: &TaskManager,
+    args: &[&str],
+    options: &serde_json::Value,
+) -> Result<String, CommandError> {
+    if args.is_empty() {
+        return Err(CommandError::invalid(
+            "Missing titles. Usage: bulk-add <title>... [--format <text|json>]",
+        ));
+    }
+
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("text");
+
+    // Check every title first so a bad one doesn't leave half the list created
+    for (i, title) in args.iter().enumerate() {
+        validate_title(title).map_err(|e| format!("Title {}: {}", i + 1, e))?;
+    }
+
+    let mut ids = Vec::with_capacity(args.len());
+    for title in args {
+        let id = with_retry(|| tasks.create_task(CreateTask::new(*title)))?;
+        ids.push(id.0);
+    }
+
+    if format == "json" {
+        return to_json(&ids, options);
+    }
+
+    let mut output = format!("Created {} tasks:\n", ids.len());
+    for (id, title) in ids.iter().zip(args) {
+        output.push_str(&format!("  #{}: {}\n", id, title));
+    }
+    Ok(output.trim_end().to_string())
+}
+
+fn cmd_show(
+    tasks: &TaskManager,
+    args: &[&str],
+    options: &serde_json::Value,
+) -> Result<String, CommandError> {
+    if args.is_empty() {
+        return Err(CommandError::invalid(
+            "Missing task ID. Usage: show <id> [--format <text|json>]",
+        ));
+    }
+
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("text");
+    let id: i64 = args[0]
+        .parse()
+        .map_err(|_| format!("Invalid task ID: {}", args[0]))?;
+    tasks
+        .get_task(TaskId(id))
+        .map_err(|_| CommandError::not_found(id))?;
+    let task_with_deps = tasks
+        .get_task_with_dependencies(TaskId(id))
+        .map_err(CommandError::store)?;
+    let task = &task_with_deps.task;
+
+    if format == "json" {
+        let mut value = serde_json::to_value(task).map_err(CommandError::internal)?;
+        if let Some(obj) = value.as_object_mut() {
+            let ids = |list: &[Task]| list.iter().map(|t| t.id.0).collect::<Vec<_>>();
+            obj.insert(
+                "depends_on".to_string(),
+                json!(ids(&task_with_deps.depends_on)),
+            );
+            obj.insert(
+                "dependents".to_string(),
+                json!(ids(&task_with_deps.dependents)),
+            );
+        }
+        return to_json(&value, options);
+    }
+
+    let mut output = format!("Task #{}\n", task.id.0);
+    output.push_str(&format!("  Title: {}\n", task.title));
+    output.push_str(&format!("  Status: {:?}\n", task.status));
+
+    if let Some(ref desc) = task.description {
+        output.push_str(&format!("  Description: {}\n", desc));
+    }
+
+    if let Some(symbol_id) = task.symbol_id {
+        output.push_str(&format!("  Linked symbol: #{}\n", symbol_id));
+    }
+
+    let scope = if task.is_global() {
+        "global"
+    } else {
+        "project"
+    };
+    output.push_str(&format!("  Scope: {}\n", scope));
+
+    if !task_with_deps.depends_on.is_empty() {
+        output.push_str("\n  Dependencies:\n");
+        for dep in &task_with_deps.depends_on {
+            output.push_str(&format!("    #{}: {}\n", dep.id.0, dep.title));
+        }
+    }
+
+    if !task_with_deps.dependents.is_empty() {
+        output.push_str("\n  Dependents:\n");
+        for dep in &task_with_deps.dependents {
+            output.push_str(&format!("    #{}: {}\n", dep.id.0, dep.title));
+        }
+    }
+
+    Ok(output.trim_end().to_string())
+}
+
+fn cmd_status(
+    tasks: &TaskManager,
+    args: &[&str],
+    options: &serde_json::Value,
+) -> Result<String, CommandError> {
+    if args.len() < 2 {
+        return Err(CommandError::invalid(
+            "Missing arguments. Usage: status <id>[,<id>...] <status> [--format <text|json>]",
+        ));
+    }
+
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("text");
+    let ids: Vec<&str> = args[0]
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .collect();
+    let status = parse_status(args[1])?;
+
+    if let [single] = ids.as_slice() {
+        if format != "json" {
+            let id: i64 = single.parse().map_err(|_| "Invalid task ID")?;
+            let task = tasks
+                .get_task(TaskId(id))
+                .map_err(|_| CommandError::not_found(id))?;
+            check_transition(task.status, status)?;
+            with_retry(|| tasks.update_status(TaskId(id), status))?;
+            let confirmation = format!("Task #{} status updated to {:?}", id, status);
+            if format == "pretty" {
+                let details = cmd_show(tasks, &[*single], &json!({}))?;
+                return Ok(format!("{}\n\n{}", confirmation, details));
+            }
+            return Ok(confirmation);
+        }
+    }
+
+    // Apply to each id independently so one bad id doesn't abort the rest
+    let mut updated = Vec::new();
+    let mut failed = Vec::new();
+    for raw in ids {
+        let result = raw
+            .parse::<i64>()
+            .map_err(|_| CommandError::invalid(format!("Invalid task ID: {}", raw)))
+            .and_then(|id| {
+                let task = tasks
+                    .get_task(TaskId(id))
+                    .map_err(|_| CommandError::new(ErrorCode::NotFound, "not found"))?;
+                check_transition(task.status, status)?;
+                with_retry(|| tasks.update_status(TaskId(id), status))?;
+                Ok(id)
+            });
+        match result {
+            Ok(id) => updated.push(id),
+            Err(error) => failed.push((raw, error)),
+        }
+    }
+
+    if format == "json" {
+        let failed: Vec<_> = failed
+            .iter()
+            .map(|(raw, error)| {
+                let id = raw
+                    .parse::<i64>()
+                    .map_or_else(|_| json!(raw), |id| json!(id));
+                json!({"id": id, "error": error.message, "code": error.code.as_str()})
+            })
+            .collect();
+        let report = json!({"updated": updated, "failed": failed});
+        return to_json(&report, options);
+    }
+
+    let mut output = format!("Updated {} tasks to {:?}\n", updated.len(), status);
+    for id in &updated {
+        output.push_str(&format!("  #{}\n", id));
+    }
+    if !failed.is_empty() {
+        output.push_str("\nFailed:\n");
+        for (raw, error) in &failed {
+            output.push_str(&format!("  {}: {}\n", raw, error));
+        }
+    }
+    Ok(output.trim_end().to_string())
+}
+
+fn cmd_reopen(tasks: &TaskManager, args: &[&str]) -> Result<String, CommandError> {
+    if args.is_empty() {
+        return Err(CommandError::invalid(
+            "Missing task ID. Usage: reopen <id> [<status>]",
+        ));
+    }
+
+    let id: i64 = args[0].parse().map_err(|_| "Invalid task ID")?;
+    let status = match args.get(1) {
+        Some(s) => parse_status(s)?,
+        None => TaskStatus::Todo,
+    };
+    if status.is_complete() {
+        return Err(CommandError::invalid(format!(
+            "Cannot reopen to {}; choose an open status",
+            status_label(status)
+        )));
+    }
+
+    let task = tasks
+        .get_task(TaskId(id))
+        .map_err(|_| CommandError::not_found(id))?;
+    if !task.status.is_complete() {
+        return Err(CommandError::invalid(format!(
+            "Task #{} is {}; only done or cancelled tasks can be reopened",
+            id,
+            status_label(task.status)
+        )));
+    }
+    check_transition(task.status, status)?;
+    with_retry(|| tasks.update_status(TaskId(id), status))?;
+
+    Ok(format!(
+        "Reopened task #{}: {} ({} -> {})",
+        id,
+        task.title,
+        status_label(task.status),
+        status_label(status)
+    ))
+}
+
+fn cmd_delete(
+    tasks: &TaskManager,
+    args: &[&str],
+    options: &serde_json::Value,
+) -> Result<String, CommandError> {
+    if args.is_empty() {
+        return Err(CommandError::invalid(
+            "Missing task ID. Usage: delete <id> [--force] [--reroute] [--dry-run] \
+             [--format <text|json>]",
+        ));
+    }
+
+    let id: i64 = args[0].parse().map_err(|_| "Invalid task ID")?;
+    let force = options
+        .get("force")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let reroute = options
+        .get("reroute")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let dry_run = options
+        .get("dry-run")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("text");
+
+    let task = tasks
+        .get_task(TaskId(id))
+        .map_err(|_| CommandError::not_found(id))?;
+    let with_deps = tasks
+        .get_task_with_dependencies(TaskId(id))
+        .map_err(CommandError::store)?;
+    let dependent_ids: Vec<String> = with_deps
+        .dependents
+        .iter()
+        .map(|d| format!("#{}", d.id.0))
+        .collect();
+
+    if !force && !dry_run {
+        let mut output = format!("Delete task #{}: {}?\n", id, task.title);
+        if !dependent_ids.is_empty() {
+            output.push_str(&format!(
+                "Tasks {} depend on it; those dependencies will be removed\n",
+                dependent_ids.join(", ")
+            ));
+        }
+        output.push_str("Use --force to confirm deletion");
+        return Ok(output);
+    }
+
+    // Wire each dependent onto each of this task's dependencies, so A <- B <- C becomes A <- C
+    let mut rerouted = Vec::new();
+    let mut skipped = Vec::new();
+    if reroute {
+        // Track added edges in memory too, so a dry run skips the same edges a real one would
+        let (_, mut deps) = load_graph(tasks)?;
+        for dependent in &with_deps.dependents {
+            for dep in &with_deps.depends_on {
+                if deps
+                    .get(&dependent.id.0)
+                    .is_some_and(|existing| existing.contains(&dep.id.0))
+                {
+                    continue;
+                }
+                if closed_cycle(&deps, dependent.id.0, dep.id.0).is_some() {
+                    skipped.push((dependent.id.0, dep.id.0));
+                    continue;
+                }
+                if !dry_run {
+                    with_retry(|| tasks.add_dependency(dependent.id, dep.id))?;
+                }
+                deps.entry(dependent.id.0).or_default().push(dep.id.0);
+                rerouted.push((dependent.id.0, dep.id.0));
+            }
+        }
+    }
+
+    if dry_run {
+        if format == "json" {
+            let report = json!({
+                "id": id,
+                "title": task.title,
+                "dependents": with_deps.dependents.iter().map(|d| d.id.0).collect::<Vec<_>>(),
+                "pruned_edges": with_deps.dependents.len() + with_deps.depends_on.len(),
+                "rerouted": rerouted,
+                "skipped": skipped,
+            });
+            return to_json(&report, options);
+        }
+
+        let mut output = format!("Would delete task #{}: {}\n", id, task.title);
+        output.push_str(&format!(
+            "Would prune {} dependency edges ({} incoming, {} outgoing)\n",
+            with_deps.dependents.len() + with_deps.depends_on.len(),
+            with_deps.dependents.len(),
+            with_deps.depends_on.len()
+        ));
+        if !dependent_ids.is_empty() {
+            output.push_str(&format!("Dependents: {}\n", dependent_ids.join(", ")));
+        }
+        if !rerouted.is_empty() {
+            output.push_str("\nWould reroute dependencies:\n");
+            for (from, to) in &rerouted {
+                output.push_str(&format!("  #{} -> #{}\n", from, to));
+            }
+        }
+        if !skipped.is_empty() {
+            output.push_str("\nWould skip (would create a cycle):\n");
+            for (from, to) in &skipped {
+                output.push_str(&format!("  #{} -> #{}\n", from, to));
+            }
+        }
+        return Ok(output.trim_end().to_string());
+    }
+
+    delete_task_pruned(tasks, TaskId(id))?;
+
+    let mut output = format!("Deleted task #{}: {}\n", id, task.title);
+    if !dependent_ids.is_empty() {
+        output.push_str(&format!(
+            "Removed dependencies from: {}\n",
+            dependent_ids.join(", ")
+        ));
+    }
+    if !rerouted.is_empty() {
+        output.push_str("\nRerouted dependencies:\n");
+        for (from, to) in &rerouted {
+            output.push_str(&format!("  #{} -> #{}\n", from, to));
+        }
+    }
+    if !skipped.is_empty() {
+        output.push_str("\nSkipped (would create a cycle):\n");
+        for (from, to) in &skipped {
+            output.push_str(&format!("  #{} -> #{}\n", from, to));
+        }
+    }
+    Ok(output.trim_end().to_string())
+}
+
+fn cmd_merge(
+    tasks: &TaskManager,
+    args: &[&str],
+    options: &serde_json::Value,
+) -> Result<String, CommandError> {
+    if args.len() < 2 {
+        return Err(CommandError::invalid(
+            "Missing arguments. Usage: merge <source-id> <target-id> [--force]",
+        ));
+    }
+
+    let source: i64 = args[0].parse().map_err(|_| "Invalid source ID")?;
+    let target: i64 = args[1].parse().map_err(|_| "Invalid target ID")?;
+    let force = options
+        .get("force")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    if source == target {
+        return Err(CommandError::invalid(format!(
+            "Task #{} cannot be merged into itself",
+            source
+        )));
+    }
+    let source_task = tasks
+        .get_task(TaskId(source))
+        .map_err(|_| CommandError::not_found(source))?;
+    let target_task = tasks
+        .get_task(TaskId(target))
+        .map_err(|_| CommandError::not_found(target))?;
+
+    // The target takes over the source's dependencies, and the source's dependents
+    // depend on the target instead
+    let (_, deps) = load_graph(tasks)?;
+    let no_deps = Vec::new();
+    let target_deps = deps.get(&target).unwrap_or(&no_deps);
+    let moved_deps: Vec<i64> = deps
+        .get(&source)
+        .unwrap_or(&no_deps)
+        .iter()
+        .filter(|d| **d != target && !target_deps.contains(*d))
+        .copied()
+        .collect();
+    let mut source_dependents: Vec<i64> = deps
+        .iter()
+        .filter(|(_, task_deps)| task_deps.contains(&source))
+        .map(|(id, _)| *id)
+        .collect();
+    source_dependents.sort_unstable();
+    let moved_dependents: Vec<i64> = source_dependents
+        .iter()
+        .filter(|id| **id != target && !deps[*id].contains(&target))
+        .copied()
+        .collect();
+
+    let mut merged = deps.clone();
+    merged.remove(&source);
+    for task_deps in merged.values_mut() {
+        task_deps.retain(|d| *d != source);
+    }
+    merged.entry(target).or_default().extend(&moved_deps);
+    for id in &moved_dependents {
+        merged.entry(*id).or_default().push(target);
+    }
+    // Every new edge touches the target, so only cycles through it can be new; an
+    // unrelated cycle elsewhere in the store doesn't block the merge
+    let mut merged_target_deps: Vec<i64> = merged.get(&target).cloned().unwrap_or_default();
+    merged_target_deps.sort_unstable();
+    if let Some(cycle) = merged_target_deps
+        .iter()
+        .find_map(|dep| closed_cycle(&merged, target, *dep))
+    {
+        return Err(CommandError::cycle(format!(
+            "Merging would create a cycle: {}",
+            format_cycle(&cycle)
+        )));
+    }
+
+    let ids = |list: &[i64]| {
+        list.iter()
+            .map(|id| format!("#{}", id))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut changes = String::new();
+    if !moved_deps.is_empty() {
+        changes.push_str(&format!(
+            "  #{} now depends on {}\n",
+            target,
+            ids(&moved_deps)
+        ));
+    }
+    if !moved_dependents.is_empty() {
+        changes.push_str(&format!(
+            "  {} now depend on #{}\n",
+            ids(&moved_dependents),
+            target
+        ));
+    }
+
+    if !force {
+        return Ok(format!(
+            "Merge task #{}: {} into #{}: {}?\n{}Use --force to confirm the merge",
+            source, source_task.title, target, target_task.title, changes
+        ));
+    }
+
+    for dep in &moved_deps {
+        with_retry(|| tasks.add_dependency(TaskId(target), TaskId(*dep)))?;
+    }
+    for id in &moved_dependents {
+        with_retry(|| tasks.add_dependency(TaskId(*id), TaskId(target)))?;
+    }
+    delete_task_pruned(tasks, TaskId(source))?;
+
+    Ok(format!(
+        "Merged task #{}: {} into #{}: {}\n{}",
+        source, source_task.title, target, target_task.title, changes
+    )
+    .trim_end()
+    .to_string())
+}
+
+fn cmd_clear_completed(
+    tasks: &TaskManager,
+    options: &serde_json::Value,
+) -> Result<String, CommandError> {
+    let force = options
+        .get("force")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let done = tasks
+        .get_by_status(TaskStatus::Done)
+        .map_err(CommandError::store)?;
+    if done.is_empty() {
+        return Ok("No completed tasks".to_string());
+    }
+
+    if !force {
+        let mut output = format!("Delete {} completed tasks?\n", done.len());
+        for task in &done {
+            output.push_str(&format!("  #{}: {}\n", task.id.0, task.title));
+        }
+        output.push_str("Use --force to confirm deletion");
+        return Ok(output);
+    }
+
+    let mut output = format!("Deleted {} completed tasks:\n", done.len());
+    let mut pruned = Vec::new();
+    for task in &done {
+        pruned.extend(delete_task_pruned(tasks, task.id)?);
+        output.push_str(&format!("  #{}: {}\n", task.id.0, task.title));
+    }
+    // Edges between two cleared tasks don't matter; report the tasks that remain
+    let cleared: HashSet<i64> = done.iter().map(|t| t.id.0).collect();
+    pruned.retain(|id| !cleared.contains(id));
+    pruned.sort_unstable();
+    pruned.dedup();
+    if !pruned.is_empty() {
+        let pruned: Vec<String> = pruned.iter().map(|id| format!("#{}", id)).collect();
+        output.push_str(&format!(
+            "Removed dependencies from: {}\n",
+            pruned.join(", ")
+        ));
+    }
+    Ok(output.trim_end().to_string())
+}
+
+/// Delete a task after dropping every edge into it
+///
+/// Edges are removed explicitly so no dependent is left pointing at a missing id.
+/// Returns the ids of the dependents that lost an edge.
+fn delete_task_pruned(tasks: &TaskManager, id: TaskId) -> Result<Vec<i64>, CommandError> {
+    let dependents = tasks
+        .get_task_with_dependencies(id)
+        .map_err(CommandError::store)?
+        .dependents;
+    for dependent in &dependents {
+        with_retry(|| tasks.remove_dependency(dependent.id, id))?;
+    }
+    with_retry(|| tasks.delete_task(id))?;
+    Ok(dependents.iter().map(|d| d.id.0).collect())
+}
+
+fn cmd_depend(tasks: &TaskManager, args: &[&str]) -> Result<String, CommandError> {
+    if args.len() < 2 {
+        return Err(CommandError::invalid(
+            "Missing arguments. Usage: depend <task-id> <depends-on-id>",
+        ));
+    }
+
+    let task_id: i64 = args[0].parse().map_err(|_| "Invalid task ID")?;
+    let depends_on: i64 = args[1].parse().map_err(|_| "Invalid depends-on ID")?;
+
+    if task_id == depends_on {
+        return Err(CommandError::invalid(format!(
+            "Task #{} cannot depend on itself",
+            task_id
+        )));
+    }
+    for id in [task_id, depends_on] {
+        tasks
+            .get_task(TaskId(id))
+            .map_err(|_| CommandError::not_found(id))?;
+    }
+    // The new edge closes a cycle if depends-on already (transitively) depends on the task
+    let (_, deps) = load_graph(tasks)?;
+    if let Some(cycle) = closed_cycle(&deps, task_id, depends_on) {
+        return Err(CommandError::cycle(format!(
+            "Adding this dependency would create a cycle: {}",
+            format_cycle(&cycle)
+        )));
+    }
+
+    with_retry(|| tasks.add_dependency(TaskId(task_id), TaskId(depends_on)))?;
+    Ok(format!(
+        "Task #{} now depends on task #{}",
+        task_id, depends_on
+    ))
+}
+
+fn cmd_undepend(tasks: &TaskManager, args: &[&str]) -> Result<String, CommandError> {
+    if args.len() < 2 {
+        return Err(CommandError::invalid(
+            "Missing arguments. Usage: undepend <task-id> <depends-on-id>",
+        ));
+    }
+
+    let task_id: i64 = args[0].parse().map_err(|_| "Invalid task ID")?;
+    let depends_on: i64 = args[1].parse().map_err(|_| "Invalid depends-on ID")?;
+
+    for id in [task_id, depends_on] {
+        tasks
+            .get_task(TaskId(id))
+            .map_err(|_| CommandError::not_found(id))?;
+    }
+    let existing = tasks
+        .get_dependencies(TaskId(task_id))
+        .map_err(CommandError::store)?;
+    if !existing.iter().any(|d| d.id.0 == depends_on) {
+        return Err(CommandError::invalid(format!(
+            "Task #{} does not depend on task #{}",
+            task_id, depends_on
+        )));
+    }
+
+    with_retry(|| tasks.remove_dependency(TaskId(task_id), TaskId(depends_on)))?;
+    Ok(format!(
+        "Removed dependency: #{} -> #{}",
+        task_id, depends_on
+    ))
+}
+
+fn cmd_redepend(tasks: &TaskManager, args: &[&str]) -> Result<String, CommandError> {
+    if args.len() < 3 {
+        return Err(CommandError::invalid(
+            "Missing arguments. Usage: redepend <task-id> <old-depends-on-id> <new-depends-on-id>",
+        ));
+    }
+
+    let task_id: i64 = args[0].parse().map_err(|_| "Invalid task ID")?;
+    let old_dep: i64 = args[1].parse().map_err(|_| "Invalid old depends-on ID")?;
+    let new_dep: i64 = args[2].parse().map_err(|_| "Invalid new depends-on ID")?;
+
+    if task_id == new_dep {
+        return Err(CommandError::invalid(format!(
+            "Task #{} cannot depend on itself",
+            task_id
+        )));
+    }
+    for id in [task_id, new_dep] {
+        tasks
+            .get_task(TaskId(id))
+            .map_err(|_| CommandError::not_found(id))?;
+    }
+    let existing = tasks
+        .get_dependencies(TaskId(task_id))
+        .map_err(CommandError::store)?;
+    if !existing.iter().any(|d| d.id.0 == old_dep) {
+        return Err(CommandError::invalid(format!(
+            "Task #{} does not depend on task #{}",
+            task_id, old_dep
+        )));
+    }
+    if existing.iter().any(|d| d.id.0 == new_dep) {
+        return Err(CommandError::invalid(format!(
+            "Task #{} already depends on task #{}",
+            task_id, new_dep
+        )));
+    }
+    // Any path back to the task ends at it, so the edge being removed can't be part of one
+    let (_, deps) = load_graph(tasks)?;
+    if let Some(cycle) = closed_cycle(&deps, task_id, new_dep) {
+        return Err(CommandError::cycle(format!(
+            "Moving this dependency would create a cycle: {}",
+            format_cycle(&cycle)
+        )));
+    }
+
+    // Add before removing, and undo the add if the removal fails, so the move is all or nothing
+    with_retry(|| tasks.add_dependency(TaskId(task_id), TaskId(new_dep)))?;
+    if let Err(e) = with_retry(|| tasks.remove_dependency(TaskId(task_id), TaskId(old_dep))) {
+        with_retry(|| tasks.remove_dependency(TaskId(task_id), TaskId(new_dep)))?;
+        return Err(e);
+    }
+    Ok(format!(
+        "Moved dependency: #{} -> #{} is now #{} -> #{}",
+        task_id, old_dep, task_id, new_dep
+    ))
+}
+
+fn cmd_graph(tasks: &TaskManager, options: &serde_json::Value) -> Result<String, CommandError> {
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("text");
+    if format == "json-graph" {
+        let (all_tasks, deps) = load_graph(tasks)?;
+        let nodes: Vec<_> = all_tasks
+            .iter()
+            .map(|t| json!({"id": t.id.0, "title": t.title, "status": t.status}))
+            .collect();
+        let edges: Vec<_> = all_tasks
+            .iter()
+            .flat_map(|t| {
+                deps.get(&t.id.0)
+                    .into_iter()
+                    .flatten()
+                    .map(move |dep| json!({"from": t.id.0, "to": dep}))
+            })
+            .collect();
+        let graph = json!({
+            "nodes": nodes,
+            "edges": edges,
+            "acyclic": find_cycle(&deps).is_none()
+        });
+        return to_json(&graph, options);
+    }
+
+    let all_tasks = tasks.list().map_err(CommandError::store)?;
+
+    if format == "json" {
+        let mut graph_data = Vec::new();
+        for task in &all_tasks {
+            let deps = tasks
+                .get_dependencies(task.id)
+                .map_err(CommandError::store)?;
+            graph_data.push(json!({
+                "task": task,
+                "dependencies": deps.iter().map(|d| d.id.0).collect::<Vec<_>>()
+            }));
+        }
+        return to_json(&graph_data, options);
+    }
+
+    if format == "dot" {
+        let mut output = String::from("digraph tasks {\n  rankdir=LR;\n");
+        for task in &all_tasks {
+            let label = dot_escape(&format!("#{}: {}", task.id.0, task.title));
+            let color = match task.status {
+                TaskStatus::Done => "green",
+                TaskStatus::InProgress => "blue",
+                TaskStatus::Blocked => "red",
+                TaskStatus::Cancelled => "gray",
+                TaskStatus::Todo => "black",
+            };
+            output.push_str(&format!(
+                "  {} [label=\"{}\" color=\"{}\"];\n",
+                task.id.0, label, color
+            ));
+
+            let deps = tasks
+                .get_dependencies(task.id)
+                .map_err(CommandError::store)?;
+            for dep in deps {
+                output.push_str(&format!("  {} -> {};\n", task.id.0, dep.id.0));
+            }
+        }
+        output.push_str("}\n");
+        return Ok(output);
+    }
+
+    // Text format
+    if all_tasks.is_empty() {
+        return Ok("No tasks found".to_string());
+    }
+
+    let mut output = String::from("Task Dependency Graph\n\n");
+    for task in &all_tasks {
+        let status_icon = status_icon(task.status);
+        output.push_str(&format!("{} #{} {}\n", status_icon, task.id.0, task.title));
+
+        let deps = tasks
+            .get_dependencies(task.id)
+            .map_err(CommandError::store)?;
+        for (i, dep) in deps.iter().enumerate() {
+            let prefix = if i == deps.len() - 1 {
+                "  └─"
+            } else {
+                "  ├─"
+            };
+            output.push_str(&format!(
+                "{} depends on #{}: {}\n",
+                prefix, dep.id.0, dep.title
+            ));
+        }
+    }
+    Ok(output.trim_end().to_string())
+}
+
+fn cmd_search(
+    tasks: &TaskManager,
+    args: &[&str],
+    options: &serde_json::Value,
+) -> Result<String, CommandError> {
+    if args.is_empty() {
+        return Err(CommandError::invalid(
+            "Missing query. Usage: search <query> [--limit <n>] [--status <status>]",
+        ));
+    }
+
+    let query = args[0];
+    let limit = options
+        .get("limit")
+        .and_then(|v| v.as_str())
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(10usize);
+    let statuses = options
+        .get("status")
+        .and_then(|v| v.as_str())
+        .map(parse_status_set)
+        .transpose()?;
+
+    let results = match statuses {
+        Some(statuses) => {
+            // Search the whole store so the status filter doesn't eat into the limit
+            let total = tasks.list().map_err(CommandError::store)?.len();
+            tasks
+                .search(query, total.max(limit))
+                .map_err(CommandError::store)?
+                .into_iter()
+                .filter(|t| statuses.contains(&t.status))
+                .take(limit)
+                .collect()
+        }
+        None => tasks.search(query, limit).map_err(CommandError::store)?,
+    };
+
+    if results.is_empty() {
+        return Ok("No tasks found".to_string());
+    }
+
+    let mut output = format!("Found {} results for \"{}\":\n\n", results.len(), query);
+    for task in results {
+        let status_icon = status_icon(task.status);
+        output.push_str(&format!("{} #{} {}\n", status_icon, task.id.0, task.title));
+    }
+    Ok(output.trim_end().to_string())
+}
+
+fn cmd_symbol(
+    tasks: &TaskManager,
+    args: &[&str],
+    options: &serde_json::Value,
+) -> Result<String, CommandError> {
+    if args.is_empty() {
+        return Err(CommandError::invalid(
+            "Missing symbol ID. Usage: symbol <symbol-id> [--format <text|json>]",
+        ));
+    }
+
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("text");
+    let symbol_id: i64 = args[0]
+        .parse()
+        .map_err(|_| format!("Invalid symbol ID: {}", args[0]))?;
+
+    let linked: Vec<Task> = tasks
+        .list()
+        .map_err(CommandError::store)?
+        .into_iter()
+        .filter(|t| t.symbol_id == Some(symbol_id))
+        .collect();
+
+    if format == "json" {
+        return to_json(&linked, options);
+    }
+
+    if linked.is_empty() {
+        return Ok(format!("No tasks linked to symbol #{}", symbol_id));
+    }
+
+    let mut output = format!("Tasks linked to symbol #{}:\n\n", symbol_id);
+    for task in linked {
+        output.push_str(&format!(
+            "{} #{} {}\n",
+            status_icon(task.status),
+            task.id.0,
+            task.title
+        ));
+    }
+    Ok(output.trim_end().to_string())
+}
+
+fn cmd_blocked(tasks: &TaskManager, options: &serde_json::Value) -> Result<String, CommandError> {
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("text");
+
+    let (all_tasks, deps) = load_graph(tasks)?;
+    let blocked = blocked_tasks(&all_tasks, &deps);
+
+    if format == "json" {
+        let detail: Vec<_> = blocked
+            .iter()
+            .map(|(task, unsatisfied)| {
+                let mut reasons: Vec<_> = unsatisfied
+                    .iter()
+                    .map(|d| json!({"type": "dependency", "id": d.id.0, "status": d.status}))
+                    .collect();
+                if task.status == TaskStatus::Blocked {
+                    reasons.push(json!({"type": "manual", "reason": "status set to blocked"}));
+                }
+                json!({
+                    "task": task,
+                    "unsatisfied": unsatisfied.iter().map(|d| d.id.0).collect::<Vec<_>>(),
+                    "reasons": reasons
+                })
+            })
+            .collect();
+        return to_json(&detail, options);
+    }
+
+    if blocked.is_empty() {
+        return Ok("No blocked tasks".to_string());
+    }
+
+    let mut output = String::from("Blocked Tasks\n\n");
+    for (task, unsatisfied) in blocked {
+        output.push_str(&format!("✕ #{} {}\n", task.id.0, task.title));
+
+        for blocker in unsatisfied {
+            output.push_str(&format!(
+                "  └─ blocked by #{}: {} ({:?})\n",
+                blocker.id.0, blocker.title, blocker.status
+            ));
+        }
+        if task.status == TaskStatus::Blocked {
+            output.push_str("  └─ manually marked as blocked\n");
+        }
+    }
+    Ok(output.trim_end().to_string())
+}
+
+/// Open tasks that are blocked, each with its incomplete dependencies
+///
+/// A task is blocked while any dependency is incomplete, or when marked Blocked by hand.
+/// `blocked`, `list --blocked` and `can-start` all go through this so they agree.
+fn blocked_tasks<'a>(
+    all_tasks: &'a [Task],
+    deps: &HashMap<i64, Vec<i64>>,
+) -> Vec<(&'a Task, Vec<&'a Task>)> {
+    let by_id: HashMap<i64, &Task> = all_tasks.iter().map(|t| (t.id.0, t)).collect();
+    let mut blocked = Vec::new();
+    for task in all_tasks.iter().filter(|t| !t.status.is_complete()) {
+        let unsatisfied: Vec<&Task> = deps
+            .get(&task.id.0)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| by_id.get(id).copied())
+            .filter(|d| !d.status.is_complete())
+            .collect();
+        if !unsatisfied.is_empty() || task.status == TaskStatus::Blocked {
+            blocked.push((task, unsatisfied));
+        }
+    }
+    blocked
+}
+
+fn cmd_can_start(
+    tasks: &TaskManager,
+    args: &[&str],
+    options: &serde_json::Value,
+) -> Result<String, CommandError> {
+    if args.is_empty() {
+        return Err(CommandError::invalid(
+            "Missing task ID. Usage: can-start <id> [--format <text|json>]",
+        ));
+    }
+
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("text");
+    let id: i64 = args[0]
+        .parse()
+        .map_err(|_| format!("Invalid task ID: {}", args[0]))?;
+    let task = tasks
+        .get_task(TaskId(id))
+        .map_err(|_| CommandError::not_found(id))?;
+
+    let (all_tasks, deps) = load_graph(tasks)?;
+    let blocked = blocked_tasks(&all_tasks, &deps);
+    let blocking: Vec<i64> = blocked
+        .iter()
+        .find(|(t, _)| t.id.0 == id)
+        .map(|(_, unsatisfied)| unsatisfied.iter().map(|d| d.id.0).collect())
+        .unwrap_or_default();
+    let manual = task.status == TaskStatus::Blocked;
+
+    if format == "json" {
+        let result = json!({
+            "can_start": blocking.is_empty() && !manual,
+            "blocking": blocking,
+            "manually_blocked": manual
+        });
+        return to_json(&result, options);
+    }
+
+    let blocking: Vec<String> = blocking.iter().map(|d| format!("#{}", d)).collect();
+    Ok(match (blocking.is_empty(), manual) {
+        (true, false) => format!("Task #{} can start", id),
+        (true, true) => format!("Task #{} is manually marked as blocked", id),
+        (false, false) => format!("Task #{} is waiting on {}", id, blocking.join(", ")),
+        (false, true) => format!(
+            "Task #{} is waiting on {} and is manually marked as blocked",
+            id,
+            blocking.join(", ")
+        ),
+    })
+}
+
+fn cmd_cycles(tasks: &TaskManager) -> Result<String, CommandError> {
+    let cycles = tasks.detect_cycles().map_err(CommandError::store)?;
+
+    if cycles.is_empty() {
+        return Ok("No circular dependencies detected".to_string());
+    }
+
+    let mut output = format!("Found {} circular dependencies:\n\n", cycles.len());
+    for (i, cycle) in cycles.iter().enumerate() {
+        output.push_str(&format!("  Cycle {}: ", i + 1));
+        let cycle_str = cycle
+            .iter()
+            .map(|id| format!("#{}", id.0))
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        output.push_str(&format!(
+            "{} -> #{}\n",
+            cycle_str,
+            cycle.first().map(|id| id.0).unwrap_or(0)
+        ));
+    }
+    Ok(output.trim_end().to_string())
+}
+
+fn cmd_plan(tasks: &TaskManager, options: &serde_json::Value) -> Result<String, CommandError> {
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("text");
+
+    // Only open work is planned; completed dependencies are already satisfied
+    let (all_tasks, deps) = load_graph(tasks)?;
+    let (open, open_deps) = open_graph(&all_tasks, &deps);
+
+    let waves = plan_waves(&open_deps).map_err(|cycle| {
+        CommandError::cycle(format!(
+            "Cannot plan: dependency cycle {}",
+            format_cycle(&cycle)
+        ))
+    })?;
+
+    if format == "json" {
+        let order: Vec<i64> = waves.iter().flatten().copied().collect();
+        let plan = json!({"waves": waves, "order": order});
+        return to_json(&plan, options);
+    }
+
+    if waves.is_empty() {
+        return Ok("No open tasks".to_string());
+    }
+
+    let mut output = String::from("Execution Plan\n");
+    for (i, wave) in waves.iter().enumerate() {
+        output.push_str(&format!("\nWave {}:\n", i + 1));
+        for id in wave {
+            let task = open[id];
+            output.push_str(&format!(
+                "  {} #{} {}\n",
+                status_icon(task.status),
+                task.id.0,
+                task.title
+            ));
+        }
+    }
+    Ok(output.trim_end().to_string())
+}
+
+fn cmd_critical(tasks: &TaskManager, options: &serde_json::Value) -> Result<String, CommandError> {
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("text");
+
+    // Completed work no longer constrains the schedule
+    let (all_tasks, deps) = load_graph(tasks)?;
+    let (open, open_deps) = open_graph(&all_tasks, &deps);
+
+    if let Some(cycle) = find_cycle(&open_deps) {
+        return Err(CommandError::cycle(format!(
+            "Cannot find critical path: dependency cycle {}",
+            format_cycle(&cycle)
+        )));
+    }
+
+    let depths = chain_depths(&open_deps);
+    // The chain ends at the deepest task; ties go to the lowest id
+    let end = depths
+        .iter()
+        .max_by_key(|(id, (len, _))| (*len, std::cmp::Reverse(**id)))
+        .map(|(id, _)| *id);
+    let mut path = Vec::new();
+    let mut next = end;
+    while let Some(id) = next {
+        path.push(id);
+        next = depths[&id].1;
+    }
+    // Walked from the last task back to its deepest dependency; emit in working order
+    path.reverse();
+
+    if format == "json" {
+        let critical = json!({"length": path.len(), "path": path});
+        return to_json(&critical, options);
+    }
+
+    if path.is_empty() {
+        return Ok("No open tasks".to_string());
+    }
+
+    let mut output = format!("Critical Path ({} tasks)\n\n", path.len());
+    for (i, id) in path.iter().enumerate() {
+        let task = open[id];
+        output.push_str(&format!(
+            "  {}. {} #{} {}\n",
+            i + 1,
+            status_icon(task.status),
+            task.id.0,
+            task.title
+        ));
+    }
+    Ok(output.trim_end().to_string())
+}
+
+fn cmd_structure(tasks: &TaskManager, options: &serde_json::Value) -> Result<String, CommandError> {
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("text");
+
+    let (all_tasks, deps) = load_graph(tasks)?;
+    if let Some(cycle) = find_cycle(&deps) {
+        return Err(CommandError::cycle(format!(
+            "Cannot measure: dependency cycle {}",
+            format_cycle(&cycle)
+        )));
+    }
+
+    let mut dependent_counts: HashMap<i64, usize> = HashMap::new();
+    for task in &all_tasks {
+        for dep in deps.get(&task.id.0).into_iter().flatten() {
+            *dependent_counts.entry(*dep).or_default() += 1;
+        }
+    }
+
+    let longest_chain = chain_depths(&deps)
+        .values()
+        .map(|(len, _)| *len)
+        .max()
+        .unwrap_or(0);
+    let roots = all_tasks
+        .iter()
+        .filter(|t| !dependent_counts.contains_key(&t.id.0))
+        .count();
+    let leaves = all_tasks
+        .iter()
+        .filter(|t| deps.get(&t.id.0).into_iter().flatten().next().is_none())
+        .count();
+    // Ties go to the lowest id so the answer is stable
+    let max_fan_out = all_tasks
+        .iter()
+        .filter_map(|t| dependent_counts.get(&t.id.0).map(|n| (t, *n)))
+        .max_by_key(|(t, n)| (*n, std::cmp::Reverse(t.id.0)));
+
+    if format == "json" {
+        let structure = json!({
+            "tasks": all_tasks.len(),
+            "longest_chain": longest_chain,
+            "roots": roots,
+            "leaves": leaves,
+            "max_fan_out": max_fan_out.map(|(t, n)| json!({"id": t.id.0, "dependents": n})),
+        });
+        return to_json(&structure, options);
+    }
+
+    let mut output = String::from("Dependency Structure\n\n");
+    output.push_str(&format!("  Longest chain:   {}\n", longest_chain));
+    output.push_str(&format!("  Roots:           {}\n", roots));
+    output.push_str(&format!("  Leaves:          {}\n", leaves));
+    match max_fan_out {
+        Some((task, n)) => output.push_str(&format!(
+            "  Max fan-out:     #{} {} ({} dependents)\n",
+            task.id.0, task.title, n
+        )),
+        None => output.push_str("  Max fan-out:     none\n"),
+    }
+    Ok(output.trim_end().to_string())
+}
+
+fn cmd_stats(tasks: &TaskManager, options: &serde_json::Value) -> Result<String, CommandError> {
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("text");
+    let status = tasks.status().map_err(CommandError::store)?;
+    let all_tasks = tasks.list().map_err(CommandError::store)?;
+    let ready_count = tasks.get_ready().map_err(CommandError::store)?.len();
+
+    let (mut todo, mut in_progress, mut done, mut blocked, mut cancelled) = (0, 0, 0, 0, 0);
+    for task in &all_tasks {
+        match task.status {
+            TaskStatus::Todo => todo += 1,
+            TaskStatus::InProgress => in_progress += 1,
+            TaskStatus::Done => done += 1,
+            TaskStatus::Blocked => blocked += 1,
+            TaskStatus::Cancelled => cancelled += 1,
+        }
+    }
+    let total = all_tasks.len();
+    let completion_rate = if total == 0 {
+        0.0
+    } else {
+        done as f64 / total as f64
+    };
+
+    if format == "json" {
+        let stats = json!({
+            "total": total,
+            "todo": todo,
+            "in_progress": in_progress,
+            "done": done,
+            "blocked": blocked,
+            "cancelled": cancelled,
+            "ready": ready_count,
+            "completion_rate": completion_rate,
+            "dependencies": status.total_dependencies,
+            "has_cycles": status.has_cycles
+        });
+        return to_json(&stats, options);
+    }
+
+    let mut output = String::from("Task Statistics\n\n");
+    output.push_str(&format!("  Total tasks:     {}\n", total));
+    output.push_str(&format!("  Todo:            {}\n", todo));
+    output.push_str(&format!("  In Progress:     {}\n", in_progress));
+    output.push_str(&format!("  Done:            {}\n", done));
+    output.push_str(&format!("  Blocked:         {}\n", blocked));
+    output.push_str(&format!("  Cancelled:       {}\n", cancelled));
+    output.push_str(&format!("\n  Ready:           {}\n", ready_count));
+    output.push_str(&format!(
+        "  Completion:      {:.0}%\n",
+        completion_rate * 100.0
+    ));
+    output.push_str(&format!(
+        "\n  Dependencies:    {}\n",
+        status.total_dependencies
+    ));
+
+    if status.has_cycles {
+        output.push_str("  Cycles:          Yes (run 'cycles' to see)\n");
+    } else {
+        output.push_str("  Cycles:          None\n");
+    }
+
+    Ok(output.trim_end().to_string())
+}
+
+fn cmd_count(tasks: &TaskManager, options: &serde_json::Value) -> Result<String, CommandError> {
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("text");
+    let statuses = options
+        .get("status")
+        .and_then(|v| v.as_str())
+        .map(parse_status_set)
+        .transpose()?;
+
+    let count = tasks
+        .list()
+        .map_err(CommandError::store)?
+        .iter()
+        .filter(|t| match &statuses {
+            Some(statuses) => statuses.contains(&t.status),
+            None => true,
+        })
+        .count();
+
+    if format == "json" {
+        return to_json(&json!({ "count": count }), options);
+    }
+    Ok(count.to_string())
+}
+
+fn cmd_export(tasks: &TaskManager, options: &serde_json::Value) -> Result<String, CommandError> {
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("bundle");
+    let statuses = options
+        .get("status")
+        .and_then(|v| v.as_str())
+        .map(parse_status_set)
+        .transpose()?;
+
+    let mut task_list: Vec<Task> = tasks
+        .list()
+        .map_err(CommandError::store)?
+        .into_iter()
+        .filter(|t| match &statuses {
+            Some(statuses) => statuses.contains(&t.status),
+            None => true,
+        })
+        .collect();
+    task_list.sort_by_key(|t| t.id.0);
+
+    match format {
+        "bundle" => {
+            // Keep only edges between exported tasks so a filtered bundle still imports
+            let exported: HashSet<i64> = task_list.iter().map(|t| t.id.0).collect();
+            let mut entries = Vec::new();
+            for task in &task_list {
+                let deps = tasks
+                    .get_dependencies(task.id)
+                    .map_err(CommandError::store)?;
+                entries.push(json!({
+                    "id": task.id.0,
+                    "title": task.title,
+                    "description": task.description,
+                    "status": task.status,
+                    "depends_on": deps
+                        .iter()
+                        .map(|d| d.id.0)
+                        .filter(|id| exported.contains(id))
+                        .collect::<Vec<_>>()
+                }));
+            }
+            let bundle = json!({
+                "format": BUNDLE_FORMAT,
+                "schema_version": BUNDLE_SCHEMA_VERSION,
+                "tasks": entries
+            });
+            to_json_unlimited(&bundle, options)
+        }
+        // Exports are backups, so they are never cut down like interactive output
+        "json" => to_json_unlimited(&task_list, options),
+        "jsonl" => {
+            let mut output = String::new();
+            for task in &task_list {
+                output.push_str(&serde_json::to_string(task).map_err(CommandError::internal)?);
+                output.push('\n');
+            }
+            Ok(output)
+        }
+        "csv" => Ok(render_csv(&task_list)),
+        "markdown" => Ok(render_markdown(&task_list)),
+        _ => Err(CommandError::invalid(format!(
+            "Unknown export format: {}",
+            format
+        ))),
+    }
+}
+
+fn cmd_import(
+    tasks: &TaskManager,
+    args: &[&str],
+    options: &serde_json::Value,
+) -> Result<String, CommandError> {
+    if args.is_empty() {
+        return Err(CommandError::invalid(
+            "Missing file. Usage: import <file> [--dry-run]",
+        ));
+    }
+
+    let dry_run = options
+        .get("dry-run")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let content = std::fs::read_to_string(args[0])
+        .map_err(|e| format!("Failed to read {}: {}", args[0], e))?;
+    let bundle: serde_json::Value =
+        serde_json::from_str(&content).map_err(|e| format!("Invalid bundle: {}", e))?;
+
+    if bundle.get("format").and_then(|v| v.as_str()) != Some(BUNDLE_FORMAT) {
+        return Err(CommandError::invalid(format!(
+            "Not an {} file",
+            BUNDLE_FORMAT
+        )));
+    }
+    let version = bundle
+        .get("schema_version")
+        .and_then(|v| v.as_u64())
+        .ok_or("Bundle is missing schema_version")?;
+    if version > BUNDLE_SCHEMA_VERSION {
+        return Err(CommandError::invalid(format!(
+            "Bundle schema version {} is newer than supported version {}",
+            version, BUNDLE_SCHEMA_VERSION
+        )));
+    }
+
+    let entries = bundle
+        .get("tasks")
+        .and_then(|v| v.as_array())
+        .ok_or("Bundle is missing tasks")?;
+
+    // Validate everything up front so a bad bundle doesn't leave a partial import
+    let mut parsed = Vec::with_capacity(entries.len());
+    for (i, entry) in entries.iter().enumerate() {
+        let old_id = entry
+            .get("id")
+            .and_then(|v| v.as_i64())
+            .ok_or_else(|| format!("Task {} is missing id", i))?;
+        let title = entry
+            .get("title")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| format!("Task #{} is missing title", old_id))?;
+        validate_title(title).map_err(|e| format!("Task #{}: {}", old_id, e))?;
+        let description = entry.get("description").and_then(|v| v.as_str());
+        let status: TaskStatus = match entry.get("status") {
+            Some(v) => serde_json::from_value(v.clone())
+                .map_err(|_| format!("Task #{} has invalid status: {}", old_id, v))?,
+            None => TaskStatus::Todo,
+        };
+        let depends_on: Vec<i64> = entry
+            .get("depends_on")
+            .and_then(|v| v.as_array())
+            .map(|arr| arr.iter().filter_map(|v| v.as_i64()).collect())
+            .unwrap_or_default();
+        parsed.push((old_id, title, description, status, depends_on));
+    }
+
+    let known: Vec<i64> = parsed.iter().map(|(id, ..)| *id).collect();
+    for (old_id, _, _, _, depends_on) in &parsed {
+        if let Some(missing) = depends_on.iter().find(|d| !known.contains(d)) {
+            return Err(CommandError::invalid(format!(
+                "Task #{} depends on #{} which is not in the bundle",
+                old_id, missing
+            )));
+        }
+    }
+    let bundle_deps: HashMap<i64, Vec<i64>> = parsed
+        .iter()
+        .map(|(id, _, _, _, depends_on)| (*id, depends_on.clone()))
+        .collect();
+    if let Some(cycle) = find_cycle(&bundle_deps) {
+        return Err(CommandError::cycle(format!(
+            "Bundle contains a dependency cycle: {}",
+            format_cycle(&cycle)
+        )));
+    }
+
+    if dry_run {
+        let mut output = format!("Would import {} tasks:\n", parsed.len());
+        for (old_id, title, _, status, depends_on) in &parsed {
+            output.push_str(&format!("  #{}: {} ({:?})", old_id, title, status));
+            if !depends_on.is_empty() {
+                let deps: Vec<String> = depends_on.iter().map(|d| format!("#{}", d)).collect();
+                output.push_str(&format!(" depends on {}", deps.join(", ")));
+            }
+            output.push('\n');
+        }
+        return Ok(output.trim_end().to_string());
+    }
+
+    let mut id_map: HashMap<i64, TaskId> = HashMap::new();
+    for (old_id, title, description, status, _) in &parsed {
+        let id = with_retry(|| {
+            let mut input = CreateTask::new(*title);
+            if let Some(desc) = description {
+                input = input.with_description(desc.to_string());
+            }
+            tasks.create_task(input)
+        })?;
+        if *status != TaskStatus::Todo {
+            with_retry(|| tasks.update_status(id, *status))?;
+        }
+        id_map.insert(*old_id, id);
+    }
+
+    for (old_id, _, _, _, depends_on) in &parsed {
+        for dep in depends_on {
+            with_retry(|| tasks.add_dependency(id_map[old_id], id_map[dep]))?;
+        }
+    }
+
+    let mut output = format!("Imported {} tasks:\n", parsed.len());
+    for (old_id, title, ..) in &parsed {
+        output.push_str(&format!(
+            "  #{} -> #{}: {}\n",
+            old_id, id_map[old_id].0, title
+        ));
+    }
+    Ok(output.trim_end().to_string())
+}
+
+/// Read a snapshot written by `export --format json` or `export --format bundle`
+fn load_snapshot(path: &str) -> Result<Vec<serde_json::Value>, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let snapshot: serde_json::Value =
+        serde_json::from_str(&content).map_err(|e| format!("Invalid snapshot {}: {}", path, e))?;
+    let entries = match snapshot.get("tasks") {
+        Some(tasks) => tasks,
+        None => &snapshot,
+    };
+    entries
+        .as_array()
+        .cloned()
+        .ok_or_else(|| format!("Snapshot {} has no task list", path))
+}
+
+/// Fields compared between snapshot entries with the same id
+const DIFF_FIELDS: [&str; 3] = ["title", "description", "status"];
+
+/// Key snapshot entries by their `id`
+fn snapshot_by_id(
+    entries: Vec<serde_json::Value>,
+) -> Result<HashMap<i64, serde_json::Value>, String> {
+    entries
+        .into_iter()
+        .map(|entry| match entry.get("id").and_then(|v| v.as_i64()) {
+            Some(id) => Ok((id, entry)),
+            None => Err("Snapshot entry is missing id".to_string()),
+        })
+        .collect()
+}
+
+/// Ids added, removed and modified between two snapshots, each sorted
+struct SnapshotDiff {
+    added: Vec<i64>,
+    removed: Vec<i64>,
+    /// Per-field `{"from", "to"}` changes for entries present in both
+    modified: Vec<(i64, serde_json::Map<String, serde_json::Value>)>,
+}
+
+/// Compare snapshot entries by id over `DIFF_FIELDS`
+fn diff_snapshots(
+    before: &HashMap<i64, serde_json::Value>,
+    after: &HashMap<i64, serde_json::Value>,
+) -> SnapshotDiff {
+    let mut added: Vec<i64> = after
+        .keys()
+        .filter(|id| !before.contains_key(id))
+        .copied()
+        .collect();
+    added.sort_unstable();
+    let mut removed: Vec<i64> = before
+        .keys()
+        .filter(|id| !after.contains_key(id))
+        .copied()
+        .collect();
+    removed.sort_unstable();
+    let mut common: Vec<i64> = before
+        .keys()
+        .filter(|id| after.contains_key(id))
+        .copied()
+        .collect();
+    common.sort_unstable();
+
+    let mut modified = Vec::new();
+    for id in common {
+        let changes: serde_json::Map<String, serde_json::Value> = DIFF_FIELDS
+            .into_iter()
+            .filter(|field| before[&id].get(*field) != after[&id].get(*field))
+            .map(|field| {
+                let change = json!({"from": before[&id].get(field), "to": after[&id].get(field)});
+                (field.to_string(), change)
+            })
+            .collect();
+        if !changes.is_empty() {
+            modified.push((id, changes));
+        }
+    }
+
+    SnapshotDiff {
+        added,
+        removed,
+        modified,
+    }
+}
+
+fn cmd_diff(args: &[&str], options: &serde_json::Value) -> Result<String, CommandError> {
+    if args.len() < 2 {
+        return Err(CommandError::invalid(
+            "Missing files. Usage: diff <before-file> <after-file> [--format <text|json>]",
+        ));
+    }
+
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("text");
+    let before = snapshot_by_id(load_snapshot(args[0])?)?;
+    let after = snapshot_by_id(load_snapshot(args[1])?)?;
+    let SnapshotDiff {
+        added,
+        removed,
+        modified,
+    } = diff_snapshots(&before, &after);
+
+    if format == "json" {
+        let diff = json!({
+            "added": added.iter().map(|id| &after[id]).collect::<Vec<_>>(),
+            "removed": removed.iter().map(|id| &before[id]).collect::<Vec<_>>(),
+            "modified": modified
+                .iter()
+                .map(|(id, changes)| json!({"id": id, "changes": changes}))
+                .collect::<Vec<_>>()
+        });
+        return to_json(&diff, options);
+    }
+
+    if added.is_empty() && removed.is_empty() && modified.is_empty() {
+        return Ok("No differences".to_string());
+    }
+
+    let title = |entry: &serde_json::Value| {
+        entry
+            .get("title")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let mut output = String::new();
+    for id in &added {
+        output.push_str(&format!("+ #{} {}\n", id, title(&after[id])));
+    }
+    for id in &removed {
+        output.push_str(&format!("- #{} {}\n", id, title(&before[id])));
+    }
+    for (id, changes) in &modified {
+        output.push_str(&format!("~ #{} {}\n", id, title(&after[id])));
+        for (field, change) in changes {
+            output.push_str(&format!(
+                "    {}: {} -> {}\n",
+                field, change["from"], change["to"]
+            ));
+        }
+    }
+    Ok(output.trim_end().to_string())
+}
+
+fn cmd_batch(
+    tasks: &TaskManager,
+    args: &[&str],
+    options: &serde_json::Value,
+) -> Result<String, CommandError> {
+    if args.is_empty() {
+        return Err(CommandError::invalid(
+            "Missing commands. Usage: batch '[[\"add\", \"Title\"], [\"show\", \"1\"]]'",
+        ));
+    }
+
+    let calls: Vec<Vec<String>> =
+        serde_json::from_str(args[0]).map_err(|e| format!("Invalid batch: {}", e))?;
+    if calls.len() > MAX_BATCH_SIZE {
+        return Err(CommandError::invalid(format!(
+            "Batch has {} commands (maximum is {})",
+            calls.len(),
+            MAX_BATCH_SIZE
+        )));
+    }
+
+    // Each command runs on its own; a failure is reported in its slot and the rest still run
+    let results: Vec<_> = calls
+        .iter()
+        .map(|call| {
+            let result = match call.first().map(|s| s.trim()) {
+                Some("batch") => Err(CommandError::invalid("batch cannot be nested")),
+                _ => run_args(tasks, call),
+            };
+            match result {
+                Ok(output) => json!({"ok": true, "output": output}),
+                Err(error) => {
+                    json!({"ok": false, "error": error.message, "code": error.code.as_str()})
+                }
+            }
+        })
+        .collect();
+    // Commands have already run, so dropping results would hide writes that happened
+    to_json_unlimited(&results, options)
+}
+
+fn cmd_validate(tasks: &TaskManager, options: &serde_json::Value) -> Result<String, CommandError> {
+    let format = options
+        .get("format")
+        .and_then(|v| v.as_str())
+        .unwrap_or("text");
+    let findings = graph_findings(tasks)?;
+
+    if format == "json" {
+        return to_json(&findings, options);
+    }
+
+    if findings.is_empty() {
+        return Ok("No problems found".to_string());
+    }
+
+    let mut output = format!("Found {} problems:\n\n", findings.len());
+    for finding in &findings {
+        output.push_str(&format!(
+            "  [{}] {}\n",
+            finding["type"].as_str().unwrap_or_default(),
+            finding["message"].as_str().unwrap_or_default()
+        ));
+    }
+    Ok(output.trim_end().to_string())
+}
+
+fn cmd_autofix(tasks: &TaskManager, options: &serde_json::Value) -> Result<String, CommandError> {
+    let dry_run = options
+        .get("dry-run")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    // Re-opening blocked tasks changes workflow state, so it only happens when asked for
+    let unblock = options
+        .get("unblock")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let mut fixed = Vec::new();
+    let mut skipped = Vec::new();
+    for finding in graph_findings(tasks)? {
+        let ids: Vec<i64> = finding["ids"]
+            .as_array()
+            .map(|ids| ids.iter().filter_map(|id| id.as_i64()).collect())
+            .unwrap_or_default();
+        match (finding["type"].as_str().unwrap_or_default(), ids.as_slice()) {
+            ("inconsistent-status", &[task_id]) if unblock => {
+                if let Err(e) = check_transition(TaskStatus::Blocked, TaskStatus::Todo) {
+                    skipped.push(format!("Task #{}: {}", task_id, e));
+                    continue;
+                }
+                if !dry_run {
+                    with_retry(|| tasks.update_status(TaskId(task_id), TaskStatus::Todo))?;
+                }
+                fixed.push(format!("Reset task #{} from Blocked to Todo", task_id));
+            }
+            ("inconsistent-status", _) => {
+                skipped.push(format!(
+                    "{} (use --unblock to reset it to Todo)",
+                    finding["message"].as_str().unwrap_or_default()
+                ));
+            }
+            // Breaking a cycle means choosing which edge to drop, which is left to a person
+            ("cycle", _) => {
+                skipped.push(finding["message"].as_str().unwrap_or_default().to_string());
+            }
+            _ => {}
+        }
+    }
+
+    if fixed.is_empty() && skipped.is_empty() {
+        return Ok("Nothing to fix".to_string());
+    }
+
+    let mut output = String::new();
+    if !fixed.is_empty() {
+        output.push_str(if dry_run { "Would fix:\n" } else { "Fixed:\n" });
+        for line in &fixed {
+            output.push_str(&format!("  {}\n", line));
+        }
+    }
+    if !skipped.is_empty() {
+        output.push_str("\nSkipped (needs manual review):\n");
+        for line in &skipped {
+            output.push_str(&format!("  {}\n", line));
+        }
+    }
+    Ok(output.trim().to_string())
+}
+
+/// Scan the whole store for integrity problems without changing anything
+///
+/// Each finding has a `type`, the `ids` involved and a human-readable `message`.
+fn graph_findings(tasks: &TaskManager) -> Result<Vec<serde_json::Value>, CommandError> {
+    let (all_tasks, deps) = load_graph(tasks)?;
+    let by_id: HashMap<i64, &Task> = all_tasks.iter().map(|t| (t.id.0, t)).collect();
+    let mut findings = Vec::new();
+
+    for cycle in tasks.detect_cycles().map_err(CommandError::store)? {
+        let ids: Vec<i64> = cycle.iter().map(|id| id.0).collect();
+        findings.push(json!({
+            "type": "cycle",
+            "message": format!("Dependency cycle: {}", format_cycle(&ids)),
+            "ids": ids
+        }));
+    }
+
+    // Inconsistent status: marked blocked although every dependency is complete. Tasks
+    // with no dependencies were blocked by hand for reasons outside the graph.
+    for task in all_tasks.iter().filter(|t| t.status == TaskStatus::Blocked) {
+        let task_deps: Vec<&&Task> = deps
+            .get(&task.id.0)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| by_id.get(id))
+            .collect();
+        if !task_deps.is_empty() && task_deps.iter().all(|d| d.status.is_complete()) {
+            findings.push(json!({
+                "type": "inconsistent-status",
+                "ids": [task.id.0],
+                "message": format!(
+                    "Task #{} ({}) is blocked but all its dependencies are complete",
+                    task.id.0, task.title
+                )
+            }));
+        }
+    }
+
+    // Unreachable: open work that no open deliverable ends up depending on
+    let roots_of = task_roots(all_tasks.iter().map(|t| t.id.0), &deps);
+    let open: HashSet<i64> = all_tasks
+        .iter()
+        .filter(|t| !t.status.is_complete())
+        .map(|t| t.id.0)
+        .collect();
+    for task in all_tasks.iter().filter(|t| open.contains(&t.id.0)) {
+        let serves_open_root = roots_of
+            .get(&task.id.0)
+            .is_some_and(|roots| roots.iter().any(|r| open.contains(r)));
+        if !serves_open_root {
+            findings.push(json!({
+                "type": "unreachable",
+                "ids": [task.id.0],
+                "message": format!(
+                    "Task #{} ({}) does not contribute to any open task",
+                    task.id.0, task.title
+                )
+            }));
+        }
+    }
+
+    Ok(findings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Mutex, MutexGuard};
+
+    /// Held by tests that change the plugin's global state, so they don't interleave
+    static GLOBALS: Mutex<()> = Mutex::new(());
+
+    fn lock_globals() -> MutexGuard<'static, ()> {
+        GLOBALS.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn graph(edges: &[(i64, &[i64])]) -> HashMap<i64, Vec<i64>> {
+        edges
+            .iter()
+            .map(|(id, deps)| (*id, deps.to_vec()))
+            .collect()
+    }
+
+    /// Open an empty store in a fresh project directory under the system temp dir
+    fn temp_store(name: &str) -> TaskManager {
+        let dir =
+            std::env::temp_dir().join(format!("adi-tasks-plugin-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        TaskManager::open(&dir).unwrap()
+    }
+
+    fn add_task(tasks: &TaskManager, title: &str) -> i64 {
+        tasks.create_task(CreateTask::new(title)).unwrap().0
+    }
+
+    fn depend(tasks: &TaskManager, task: i64, on: i64) -> Result<String, CommandError> {
+        cmd_depend(tasks, &[task.to_string().as_str(), on.to_string().as_str()])
+    }
+
+    #[test]
+    fn parse_status_accepts_todo_spellings() {
+        for input in ["todo", "TODO", "To-Do", "to do"] {
+            assert_eq!(parse_status(input), Ok(TaskStatus::Todo), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_status_accepts_in_progress_spellings() {
+        for input in [
+            "in_progress",
+            "in-progress",
+            "In Progress",
+            "inprogress",
+            "IN_PROGRESS",
+        ] {
+            assert_eq!(parse_status(input), Ok(TaskStatus::InProgress), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_status_accepts_done_spellings() {
+        for input in ["done", "Done", "DONE"] {
+            assert_eq!(parse_status(input), Ok(TaskStatus::Done), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_status_accepts_blocked_spellings() {
+        for input in ["blocked", "Blocked", "BLOCKED"] {
+            assert_eq!(parse_status(input), Ok(TaskStatus::Blocked), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_status_accepts_cancelled_spellings() {
+        for input in ["cancelled", "canceled", "Cancelled", "CANCELED"] {
+            assert_eq!(parse_status(input), Ok(TaskStatus::Cancelled), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_status_rejects_unknown_status() {
+        let err = parse_status("finished").unwrap_err();
+        assert!(err.starts_with("Invalid status: finished"), "{}", err);
+    }
+
+    #[test]
+    fn normalize_status_drops_separators_and_case() {
+        assert_eq!(normalize_status("In_Progress"), "inprogress");
+        assert_eq!(normalize_status("in-progress"), "inprogress");
+        assert_eq!(normalize_status("IN PROGRESS"), "inprogress");
+    }
+
+    #[test]
+    fn is_lock_error_matches_lock_and_busy_words() {
+        assert!(is_lock_error("database is locked"));
+        assert!(is_lock_error("Database busy, try again"));
+    }
+
+    #[test]
+    fn is_lock_error_ignores_blocked() {
+        assert!(!is_lock_error("Task #3 is blocked"));
+        assert!(!is_lock_error(
+            "Invalid status: x (expected todo, in_progress, done, blocked or cancelled)"
+        ));
+    }
+
+    #[test]
+    fn with_retry_marks_exhausted_lock_errors_retryable() {
+        let mut attempts = 0;
+        let error = with_retry(|| -> Result<(), &'static str> {
+            attempts += 1;
+            Err("database is locked")
+        })
+        .unwrap_err();
+        assert_eq!(attempts, WRITE_RETRIES.load(Ordering::Relaxed) + 1);
+        assert_eq!(error.code, ErrorCode::StoreUnavailable);
+        assert!(error.retryable);
+    }
+
+    #[test]
+    fn with_retry_returns_other_errors_at_once() {
+        let mut attempts = 0;
+        let error = with_retry(|| -> Result<(), &'static str> {
+            attempts += 1;
+            Err("Task #3 is blocked")
+        })
+        .unwrap_err();
+        assert_eq!(attempts, 1);
+        assert!(!error.retryable);
+    }
+
+    #[test]
+    fn task_roots_maps_tasks_to_the_roots_they_serve() {
+        // 3 and 4 are roots; 1 serves both through 2
+        let deps = graph(&[(1, &[]), (2, &[1]), (3, &[2]), (4, &[1])]);
+        let mut roots = task_roots([1, 2, 3, 4], &deps);
+        for r in roots.values_mut() {
+            r.sort_unstable();
+        }
+        assert_eq!(roots[&3], vec![3]);
+        assert_eq!(roots[&2], vec![3]);
+        assert_eq!(roots[&1], vec![3, 4]);
+    }
+
+    #[test]
+    fn task_roots_skips_tasks_only_reachable_through_a_cycle() {
+        let deps = graph(&[(1, &[2]), (2, &[1])]);
+        assert!(task_roots([1, 2], &deps).is_empty());
+    }
+
+    #[test]
+    fn graph_path_follows_dependencies() {
+        let deps = graph(&[(1, &[]), (2, &[1]), (3, &[2])]);
+        assert_eq!(graph_path(&deps, 3, 1), Some(vec![3, 2, 1]));
+        assert_eq!(graph_path(&deps, 1, 3), None);
+    }
+
+    #[test]
+    fn closed_cycle_starts_at_the_new_edge() {
+        // 3 -> 2 -> 1, so 1 -> 3 would close the loop
+        let deps = graph(&[(1, &[]), (2, &[1]), (3, &[2])]);
+        assert_eq!(closed_cycle(&deps, 1, 3), Some(vec![1, 3, 2]));
+        assert_eq!(closed_cycle(&deps, 3, 1), None);
+    }
+
+    #[test]
+    fn find_cycle_ignores_acyclic_graph() {
+        let deps = graph(&[(1, &[]), (2, &[1]), (3, &[1, 2])]);
+        assert_eq!(find_cycle(&deps), None);
+    }
+
+    #[test]
+    fn find_cycle_detects_direct_cycle() {
+        let deps = graph(&[(1, &[2]), (2, &[1])]);
+        assert_eq!(find_cycle(&deps), Some(vec![1, 2]));
+    }
+
+    #[test]
+    fn find_cycle_detects_three_node_cycle() {
+        let deps = graph(&[(1, &[2]), (2, &[3]), (3, &[1]), (4, &[1])]);
+        assert_eq!(find_cycle(&deps), Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn format_cycle_closes_the_loop() {
+        assert_eq!(format_cycle(&[1, 2, 3]), "#1 -> #2 -> #3 -> #1");
+    }
+
+    #[test]
+    fn depend_rejects_direct_cycle() {
+        let tasks = temp_store("depend-direct");
+        let (a, b) = (add_task(&tasks, "A"), add_task(&tasks, "B"));
+        depend(&tasks, a, b).unwrap();
+
+        let error = depend(&tasks, b, a).unwrap_err();
+        assert_eq!(error.code, ErrorCode::CycleDetected);
+        assert_eq!(
+            error.message,
+            format!(
+                "Adding this dependency would create a cycle: #{} -> #{} -> #{}",
+                b, a, b
+            )
+        );
+        assert!(tasks.get_dependencies(TaskId(b)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn depend_rejects_three_node_cycle() {
+        let tasks = temp_store("depend-three");
+        let (a, b, c) = (
+            add_task(&tasks, "A"),
+            add_task(&tasks, "B"),
+            add_task(&tasks, "C"),
+        );
+        depend(&tasks, a, b).unwrap();
+        depend(&tasks, b, c).unwrap();
+
+        let error = depend(&tasks, c, a).unwrap_err();
+        assert_eq!(error.code, ErrorCode::CycleDetected);
+        assert_eq!(
+            error.message,
+            format!(
+                "Adding this dependency would create a cycle: #{} -> #{} -> #{} -> #{}",
+                c, a, b, c
+            )
+        );
+        assert!(tasks.get_dependencies(TaskId(c)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn dot_escape_escapes_quotes_backslashes_and_newlines() {
+        assert_eq!(dot_escape("a \"b\"\\c\r\nd"), "a \\\"b\\\"\\\\c\\nd");
+    }
+
+    #[test]
+    fn graph_dot_has_one_node_per_task_and_one_edge_per_dependency() {
+        let tasks = temp_store("graph-dot");
+        let a = add_task(&tasks, "Say \"hi\"");
+        let b = add_task(&tasks, "B");
+        let c = add_task(&tasks, "C");
+        depend(&tasks, b, a).unwrap();
+        depend(&tasks, c, a).unwrap();
+        depend(&tasks, c, b).unwrap();
+
+        let dot = cmd_graph(&tasks, &json!({"format": "dot"})).unwrap();
+        assert!(dot.starts_with("digraph tasks {\n"), "{}", dot);
+        assert!(dot.ends_with("}\n"), "{}", dot);
+        assert_eq!(dot.matches(" -> ").count(), 3);
+        assert_eq!(dot.matches("[label=").count(), 3);
+        assert!(
+            dot.contains(&format!("label=\"#{}: Say \\\"hi\\\"\"", a)),
+            "{}",
+            dot
+        );
+    }
+
+    #[test]
+    fn csv_field_leaves_plain_text_alone() {
+        assert_eq!(csv_field("Write docs"), "Write docs");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn csv_field_quotes_commas() {
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+    }
+
+    #[test]
+    fn csv_field_doubles_quotes() {
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn csv_field_quotes_line_breaks() {
+        assert_eq!(csv_field("one\ntwo"), "\"one\ntwo\"");
+        assert_eq!(csv_field("one\r\ntwo"), "\"one\r\ntwo\"");
+    }
+
+    #[test]
+    fn markdown_cell_escapes_pipes() {
+        assert_eq!(markdown_cell("a|b"), "a\\|b");
+    }
+
+    #[test]
+    fn markdown_cell_replaces_line_breaks() {
+        assert_eq!(
+            markdown_cell("one\r\ntwo\nthree\rfour"),
+            "one<br>two<br>three<br>four"
+        );
+    }
+
+    #[test]
+    fn export_formats_keep_a_title_with_a_comma_and_a_quote() {
+        let tasks = temp_store("export-formats");
+        let id = add_task(&tasks, "Fix \"login\", then deploy");
+        let status = status_label(TaskStatus::Todo);
+
+        let csv = cmd_export(&tasks, &json!({"format": "csv"})).unwrap();
+        assert_eq!(
+            csv,
+            format!(
+                "id,title,status,description\n{},\"Fix \"\"login\"\", then deploy\",{},\n",
+                id, status
+            )
+        );
+
+        let markdown = cmd_export(&tasks, &json!({"format": "markdown"})).unwrap();
+        let row = format!("| {} | Fix \"login\", then deploy | {} |  |", id, status);
+        assert!(markdown.contains(&row), "{}", markdown);
+
+        let json = cmd_export(&tasks, &json!({"format": "json"})).unwrap();
+        let exported: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(exported[0]["title"], "Fix \"login\", then deploy");
+
+        let error = cmd_export(&tasks, &json!({"format": "xml"})).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidArgument);
+    }
+
+    #[test]
+    fn validate_title_rejects_empty_title() {
+        assert_eq!(
+            validate_title(""),
+            Err("Title must not be empty".to_string())
+        );
+    }
+
+    #[test]
+    fn validate_title_rejects_whitespace_only_title() {
+        assert_eq!(
+            validate_title(" \t\n "),
+            Err("Title must not be empty".to_string())
+        );
+    }
+
+    #[test]
+    fn validate_title_accepts_title_at_the_limit() {
+        let max = MAX_TITLE_LENGTH.load(Ordering::Relaxed);
+        assert_eq!(validate_title(&"x".repeat(max)), Ok(()));
+    }
+
+    #[test]
+    fn validate_title_reports_length_one_over_the_limit() {
+        let max = MAX_TITLE_LENGTH.load(Ordering::Relaxed);
+        assert_eq!(
+            validate_title(&"é".repeat(max + 1)),
+            Err(format!(
+                "Title is {} characters long (maximum is {})",
+                max + 1,
+                max
+            ))
+        );
+    }
+
+    #[test]
+    fn failed_open_is_reported_by_commands() {
+        let _globals = lock_globals();
+        // A regular file can't hold a project's task store
+        let file = std::env::temp_dir().join(format!(
+            "adi-tasks-plugin-not-a-project-{}",
+            std::process::id()
+        ));
+        std::fs::write(&file, "").unwrap();
+
+        let reason = open_store(Some(file)).expect("opening a file as a project should fail");
+        assert!(TASKS.read().unwrap().is_none());
+        assert_eq!(
+            not_initialized(),
+            format!("Tasks not initialized: {}", reason)
+        );
+
+        let error = run_cli_command(r#"{"args": ["list"]}"#).unwrap_err();
+        assert_eq!(error.code, ErrorCode::StoreUnavailable);
+        assert_eq!(error.message, format!("Tasks not initialized: {}", reason));
+    }
+
+    #[test]
+    fn plan_waves_orders_a_linear_chain() {
+        let deps = graph(&[(1, &[]), (2, &[1]), (3, &[2])]);
+        assert_eq!(plan_waves(&deps), Ok(vec![vec![1], vec![2], vec![3]]));
+    }
+
+    #[test]
+    fn plan_waves_runs_a_diamond_middle_in_parallel() {
+        let deps = graph(&[(1, &[]), (2, &[1]), (3, &[1]), (4, &[2, 3])]);
+        assert_eq!(plan_waves(&deps), Ok(vec![vec![1], vec![2, 3], vec![4]]));
+    }
+
+    #[test]
+    fn plan_waves_returns_the_cycle() {
+        let deps = graph(&[(1, &[]), (2, &[1, 3]), (3, &[2])]);
+        assert_eq!(plan_waves(&deps), Err(vec![2, 3]));
+    }
+
+    #[test]
+    fn accepted_options_come_from_usage() {
+        let accepted = accepted_options("delete").unwrap();
+        for option in ["force", "reroute", "dry-run", "format", "compact"] {
+            assert!(accepted.iter().any(|a| a == option), "{}", option);
+        }
+        assert_eq!(accepted_options("nope"), None);
+    }
+
+    #[test]
+    fn strict_options_reject_unknown_keys_and_lenient_mode_ignores_them() {
+        let _globals = lock_globals();
+        let tasks = temp_store("strict-options");
+        add_task(&tasks, "A");
+        let args: Vec<String> = ["count", "--stauts", "todo"].map(String::from).to_vec();
+
+        STRICT_OPTIONS.store(true, Ordering::Relaxed);
+        let strict = run_args(&tasks, &args);
+        STRICT_OPTIONS.store(false, Ordering::Relaxed);
+        let error = strict.unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidArgument);
+        assert!(
+            error
+                .message
+                .starts_with("Unknown options for count: --stauts (accepted: --status, --format"),
+            "{}",
+            error
+        );
+
+        assert_eq!(run_args(&tasks, &args).unwrap(), "1");
+    }
+
+    fn findings_of(tasks: &TaskManager, kind: &str) -> Vec<serde_json::Value> {
+        graph_findings(tasks)
+            .unwrap()
+            .into_iter()
+            .filter(|f| f["type"] == kind)
+            .collect()
+    }
+
+    #[test]
+    fn validate_reports_cycles() {
+        let tasks = temp_store("validate-cycle");
+        let (a, b) = (add_task(&tasks, "A"), add_task(&tasks, "B"));
+        // Written straight to the store, the way an older version could have left it
+        tasks.add_dependency(TaskId(a), TaskId(b)).unwrap();
+        tasks.add_dependency(TaskId(b), TaskId(a)).unwrap();
+
+        let cycles = findings_of(&tasks, "cycle");
+        assert_eq!(cycles.len(), 1, "{:?}", cycles);
+        let mut ids: Vec<i64> = cycles[0]["ids"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|id| id.as_i64())
+            .collect();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![a, b]);
+    }
+
+    #[test]
+    fn validate_reports_blocked_tasks_whose_dependencies_are_complete() {
+        let tasks = temp_store("validate-status");
+        let (done, waiting) = (add_task(&tasks, "Done"), add_task(&tasks, "Waiting"));
+        let manual = add_task(&tasks, "Blocked by hand");
+        depend(&tasks, waiting, done).unwrap();
+        tasks.update_status(TaskId(done), TaskStatus::Done).unwrap();
+        tasks
+            .update_status(TaskId(waiting), TaskStatus::Blocked)
+            .unwrap();
+        tasks
+            .update_status(TaskId(manual), TaskStatus::Blocked)
+            .unwrap();
+
+        let inconsistent = findings_of(&tasks, "inconsistent-status");
+        assert_eq!(inconsistent.len(), 1, "{:?}", inconsistent);
+        assert_eq!(inconsistent[0]["ids"], json!([waiting]));
+    }
+
+    #[test]
+    fn autofix_reports_cycles_without_touching_them() {
+        let _globals = lock_globals();
+        let tasks = temp_store("autofix-cycle");
+        let (a, b) = (add_task(&tasks, "A"), add_task(&tasks, "B"));
+        tasks.add_dependency(TaskId(a), TaskId(b)).unwrap();
+        tasks.add_dependency(TaskId(b), TaskId(a)).unwrap();
+
+        let output = cmd_autofix(&tasks, &json!({"unblock": true})).unwrap();
+        assert!(
+            output.contains("Skipped (needs manual review)"),
+            "{}",
+            output
+        );
+        assert!(output.contains("Dependency cycle"), "{}", output);
+        assert!(!output.contains("Fixed:"), "{}", output);
+        for (task, dep) in [(a, b), (b, a)] {
+            let deps = tasks.get_dependencies(TaskId(task)).unwrap();
+            assert_eq!(deps.iter().map(|d| d.id.0).collect::<Vec<_>>(), vec![dep]);
+        }
+    }
+
+    #[test]
+    fn autofix_resets_stale_blocked_tasks_only_with_unblock() {
+        let _globals = lock_globals();
+        let tasks = temp_store("autofix-unblock");
+        let (done, waiting) = (add_task(&tasks, "Done"), add_task(&tasks, "Waiting"));
+        depend(&tasks, waiting, done).unwrap();
+        tasks.update_status(TaskId(done), TaskStatus::Done).unwrap();
+        tasks
+            .update_status(TaskId(waiting), TaskStatus::Blocked)
+            .unwrap();
+        let status = |id| tasks.get_task(TaskId(id)).unwrap().status;
+
+        let output = cmd_autofix(&tasks, &json!({})).unwrap();
+        assert!(output.contains("use --unblock"), "{}", output);
+        assert_eq!(status(waiting), TaskStatus::Blocked);
+
+        let output = cmd_autofix(&tasks, &json!({"unblock": true, "dry-run": true})).unwrap();
+        assert!(output.starts_with("Would fix:"), "{}", output);
+        assert_eq!(status(waiting), TaskStatus::Blocked);
+
+        let output = cmd_autofix(&tasks, &json!({"unblock": true})).unwrap();
+        assert!(
+            output.contains(&format!("Reset task #{} from Blocked to Todo", waiting)),
+            "{}",
+            output
+        );
+        assert_eq!(status(waiting), TaskStatus::Todo);
+    }
+
+    #[test]
+    fn split_args_keeps_option_values_out_of_positional() {
+        let (options, positional) = split_args(&["A", "B", "--format", "json"]);
+        assert_eq!(positional, vec!["A", "B"]);
+        assert_eq!(options["format"], "json");
+    }
+
+    #[test]
+    fn split_args_treats_option_before_option_as_flag() {
+        let (options, positional) = split_args(&["7", "--force", "--format", "text"]);
+        assert_eq!(positional, vec!["7"]);
+        assert_eq!(options["force"], true);
+        assert_eq!(options["format"], "text");
+    }
+
+    #[test]
+    fn bulk_add_returns_ids_in_input_order() {
+        let tasks = temp_store("bulk-add-valid");
+        let output = cmd_bulk_add(&tasks, &["A", "B", "C"], &json!({"format": "json"})).unwrap();
+        let ids: Vec<i64> = serde_json::from_str(&output).unwrap();
+        let titles: Vec<String> = ids
+            .iter()
+            .map(|id| tasks.get_task(TaskId(*id)).unwrap().title)
+            .collect();
+        assert_eq!(titles, vec!["A", "B", "C"]);
+    }
+
+    #[test]
+    fn bulk_add_creates_nothing_when_a_title_is_invalid() {
+        let tasks = temp_store("bulk-add-invalid");
+        let error = cmd_bulk_add(&tasks, &["A", " ", "C"], &json!({})).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidArgument);
+        assert_eq!(error.message, "Title 2: Title must not be empty");
+        assert!(tasks.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn add_creates_task_in_the_requested_status() {
+        let tasks = temp_store("add-status");
+        let output = cmd_add(&tasks, &["Started"], &json!({"status": "in-progress"})).unwrap();
+        let task = tasks.list().unwrap().remove(0);
+        assert_eq!(output, format!("Created task #{}: Started", task.id.0));
+        assert_eq!(task.status, TaskStatus::InProgress);
+    }
+
+    #[test]
+    fn add_needs_force_to_create_a_done_task() {
+        let tasks = temp_store("add-done");
+        let error = cmd_add(&tasks, &["Finished"], &json!({"status": "done"})).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidArgument);
+        assert!(tasks.list().unwrap().is_empty());
+
+        cmd_add(
+            &tasks,
+            &["Finished"],
+            &json!({"status": "done", "force": true}),
+        )
+        .unwrap();
+        assert_eq!(tasks.list().unwrap()[0].status, TaskStatus::Done);
+    }
+
+    #[test]
+    fn diff_snapshots_sorts_entries_into_added_removed_and_modified() {
+        let snapshot = |entries: serde_json::Value| {
+            snapshot_by_id(entries.as_array().unwrap().clone()).unwrap()
+        };
+        let before = snapshot(json!([
+            {"id": 1, "title": "Kept", "description": null, "status": "todo"},
+            {"id": 2, "title": "Renamed", "description": null, "status": "todo"},
+            {"id": 3, "title": "Removed", "description": null, "status": "done"}
+        ]));
+        let after = snapshot(json!([
+            {"id": 1, "title": "Kept", "description": null, "status": "todo"},
+            {"id": 2, "title": "Renamed again", "description": "Now described", "status": "todo"},
+            {"id": 4, "title": "Added", "description": null, "status": "todo"}
+        ]));
+
+        let diff = diff_snapshots(&before, &after);
+        assert_eq!(diff.added, vec![4]);
+        assert_eq!(diff.removed, vec![3]);
+        assert_eq!(diff.modified.len(), 1);
+        let (id, changes) = &diff.modified[0];
+        assert_eq!(*id, 2);
+        assert_eq!(
+            serde_json::Value::Object(changes.clone()),
+            json!({
+                "title": {"from": "Renamed", "to": "Renamed again"},
+                "description": {"from": null, "to": "Now described"}
+            })
+        );
+    }
+
+    #[test]
+    fn custom_status_alias_is_accepted_by_add_and_status() {
+        let _globals = lock_globals();
+        let tasks = temp_store("status-aliases");
+        set_status_aliases(r#"{"review": "in_progress", "deferred": "blocked"}"#).unwrap();
+
+        let added = cmd_add(&tasks, &["Review me"], &json!({"status": "Review"}));
+        let id = add_task(&tasks, "Later");
+        let updated = cmd_status(&tasks, &[id.to_string().as_str(), "deferred"], &json!({}));
+        let unknown = parse_status("testing");
+        set_status_aliases("{}").unwrap();
+
+        added.unwrap();
+        updated.unwrap();
+        let status_of = |title: &str| {
+            tasks
+                .list()
+                .unwrap()
+                .into_iter()
+                .find(|t| t.title == title)
+                .unwrap()
+                .status
+        };
+        assert_eq!(status_of("Review me"), TaskStatus::InProgress);
+        assert_eq!(status_of("Later"), TaskStatus::Blocked);
+        assert!(unknown.unwrap_err().contains("or one of "));
+    }
+
+    #[test]
+    fn status_aliases_must_map_onto_built_in_statuses() {
+        let _globals = lock_globals();
+        assert_eq!(
+            set_status_aliases(r#"{"done": "todo"}"#),
+            Err("Alias done shadows a built-in status".to_string())
+        );
+        assert_eq!(
+            set_status_aliases(r#"{"review": "testing"}"#),
+            Err("Alias review must map to a built-in status, got \"testing\"".to_string())
+        );
+        assert!(STATUS_ALIASES.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn transition_rules_reject_forbidden_moves_and_allow_listed_ones() {
+        let _globals = lock_globals();
+        set_transition_rules(r#"{"todo": ["in_progress", "cancelled"]}"#).unwrap();
+        let forbidden = check_transition(TaskStatus::Todo, TaskStatus::Done);
+        let allowed = check_transition(TaskStatus::Todo, TaskStatus::InProgress);
+        let unlisted = check_transition(TaskStatus::InProgress, TaskStatus::Done);
+        set_transition_rules("{}").unwrap();
+
+        assert_eq!(
+            forbidden,
+            Err(format!(
+                "Cannot move from {} to {} (allowed: {}, {})",
+                status_label(TaskStatus::Todo),
+                status_label(TaskStatus::Done),
+                status_label(TaskStatus::InProgress),
+                status_label(TaskStatus::Cancelled)
+            ))
+        );
+        assert_eq!(allowed, Ok(()));
+        assert_eq!(unlisted, Ok(()));
+        assert_eq!(check_transition(TaskStatus::Todo, TaskStatus::Done), Ok(()));
+    }
+
+    #[test]
+    fn status_command_enforces_transition_rules() {
+        let _globals = lock_globals();
+        let tasks = temp_store("transition-rules");
+        let id = add_task(&tasks, "A");
+        let id_arg = id.to_string();
+        set_transition_rules(r#"{"todo": ["in_progress"]}"#).unwrap();
+        let skipped = cmd_status(&tasks, &[id_arg.as_str(), "done"], &json!({}));
+        let status_after_skip = tasks.get_task(TaskId(id)).unwrap().status;
+        let started = cmd_status(&tasks, &[id_arg.as_str(), "in_progress"], &json!({}));
+        set_transition_rules("{}").unwrap();
+
+        assert_eq!(skipped.unwrap_err().code, ErrorCode::InvalidArgument);
+        assert_eq!(status_after_skip, TaskStatus::Todo);
+        started.unwrap();
+        assert_eq!(
+            tasks.get_task(TaskId(id)).unwrap().status,
+            TaskStatus::InProgress
+        );
+    }
+
+    #[test]
+    fn transition_rules_must_name_known_statuses() {
+        let _globals = lock_globals();
+        let error = set_transition_rules(r#"{"todo": ["shipped"]}"#).unwrap_err();
+        assert!(error.starts_with("Invalid status: shipped"), "{}", error);
+        assert!(TRANSITION_RULES.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn catch_panics_turns_a_panic_into_an_error() {
+        let result: RResult<RString, String> =
+            catch_panics(|| panic!("{} exploded", "store"), |message| message);
+        match result {
+            RResult::RErr(message) => assert_eq!(message, "Internal error: store exploded"),
+            RResult::ROk(output) => panic!("expected an error, got {}", output),
+        }
+    }
+
+    #[test]
+    fn catch_panics_passes_results_through() {
+        let result: RResult<RString, String> =
+            catch_panics(|| RResult::ROk(RString::from("ok")), |message| message);
+        assert!(matches!(result, RResult::ROk(output) if output.as_str() == "ok"));
+    }
+
+    #[test]
+    fn panic_message_reads_str_and_string_payloads() {
+        assert_eq!(panic_message(Box::new("boom")), "Internal error: boom");
+        assert_eq!(
+            panic_message(Box::new("boom".to_string())),
+            "Internal error: boom"
+        );
+        assert_eq!(panic_message(Box::new(42)), "Internal error: unknown panic");
+    }
+
+    #[test]
+    fn to_json_truncates_lists_over_the_limit() {
+        let _globals = lock_globals();
+        let compact = json!({"compact": true});
+        let items: Vec<i64> = (0..100).collect();
+
+        MAX_OUTPUT_BYTES.store(0, Ordering::Relaxed);
+        assert_eq!(to_json(&[1, 2], &compact).unwrap(), "[1,2]");
+        assert_eq!(to_json(&[1, 2], &json!({})).unwrap(), "[\n  1,\n  2\n]");
+
+        MAX_OUTPUT_BYTES.store(120, Ordering::Relaxed);
+        let output = to_json(&items, &compact).unwrap();
+        let object = to_json(&json!({"key": "x".repeat(200)}), &compact).unwrap();
+        MAX_OUTPUT_BYTES.store(0, Ordering::Relaxed);
+
+        assert!(output.len() <= 120, "{}", output);
+        let wrapped: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(wrapped["truncated"], true);
+        assert_eq!(wrapped["total"], 100);
+        let returned = wrapped["returned"].as_u64().unwrap();
+        assert!(returned > 0);
+        assert_eq!(wrapped["items"].as_array().unwrap().len() as u64, returned);
+        assert_eq!(wrapped["items"][0], 0);
+
+        // Only lists are truncated
+        assert!(object.len() > 120);
+    }
+
+    #[test]
+    fn export_is_never_truncated() {
+        let _globals = lock_globals();
+        let tasks = temp_store("export-untruncated");
+        for i in 0..20 {
+            add_task(&tasks, &format!("Task number {}", i));
+        }
+
+        MAX_OUTPUT_BYTES.store(200, Ordering::Relaxed);
+        let json = cmd_export(&tasks, &json!({"format": "json"}));
+        let bundle = cmd_export(&tasks, &json!({}));
+        MAX_OUTPUT_BYTES.store(0, Ordering::Relaxed);
+
+        let json: serde_json::Value = serde_json::from_str(&json.unwrap()).unwrap();
+        assert_eq!(json.as_array().map(Vec::len), Some(20));
+        let bundle: serde_json::Value = serde_json::from_str(&bundle.unwrap()).unwrap();
+        assert_eq!(bundle["tasks"].as_array().map(Vec::len), Some(20));
+    }
+
+    #[test]
+    fn not_found_error_carries_code() {
+        let error = CommandError::not_found(42);
+        assert_eq!(error.code, ErrorCode::NotFound);
+        let payload: serde_json::Value = serde_json::from_str(&error.payload()).unwrap();
+        assert_eq!(payload["isError"], true);
+        assert_eq!(payload["error"]["code"], "not_found");
+        assert_eq!(payload["error"]["message"], "Task #42 not found");
+    }
+
+    #[test]
+    fn argument_errors_are_invalid_argument() {
+        let error: CommandError = parse_status("nope").unwrap_err().into();
+        assert_eq!(error.code, ErrorCode::InvalidArgument);
+        let error: CommandError = validate_title("").unwrap_err().into();
+        assert_eq!(error.code, ErrorCode::InvalidArgument);
+    }
+
+    #[test]
+    fn store_errors_split_lock_failures_from_internal_ones() {
+        assert_eq!(
+            CommandError::store("database is locked").code,
+            ErrorCode::StoreUnavailable
+        );
+        assert_eq!(
+            CommandError::store("Task #3 is blocked").code,
+            ErrorCode::Internal
+        );
+    }
+
+    #[test]
+    fn undepend_reports_missing_tasks_and_edges_with_stable_codes() {
+        let tasks = temp_store("undepend-codes");
+        let (a, b) = (add_task(&tasks, "A"), add_task(&tasks, "B"));
+        let undepend = |task: i64, on: i64| {
+            cmd_undepend(
+                &tasks,
+                &[task.to_string().as_str(), on.to_string().as_str()],
+            )
+        };
+
+        let missing = b + 100;
+        let error = undepend(a, missing).unwrap_err();
+        assert_eq!(error.code, ErrorCode::NotFound);
+        assert_eq!(error.message, format!("Task #{} not found", missing));
+
+        let error = undepend(a, b).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidArgument);
+
+        depend(&tasks, a, b).unwrap();
+        undepend(a, b).unwrap();
+        assert!(tasks.get_dependencies(TaskId(a)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn project_from_env_is_opened() {
+        let _globals = lock_globals();
+        let dir = std::env::temp_dir().join(format!(
+            "adi-tasks-plugin-env-project-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        std::env::set_var(PROJECT_ENV, "");
+        let unset = env_project();
+        std::env::set_var(PROJECT_ENV, &dir);
+        let project = env_project();
+        std::env::remove_var(PROJECT_ENV);
+        assert_eq!(unset, None);
+        assert_eq!(project.as_ref(), Some(&dir));
+
+        assert_eq!(open_store(project), None);
+        assert!(TASKS.read().unwrap().is_some());
+        match dispatch_message(RStr::from("get_project_path"), RStr::from("")) {
+            RResult::ROk(path) => assert_eq!(path.as_str(), dir.display().to_string()),
+            RResult::RErr(_) => panic!("get_project_path failed"),
+        }
+    }
+
+    #[test]
+    fn chain_depths_measures_longest_chain() {
+        let deps = graph(&[(1, &[]), (2, &[1]), (3, &[2]), (4, &[1])]);
+        let depths = chain_depths(&deps);
+        assert_eq!(depths[&1], (1, None));
+        assert_eq!(depths[&2], (2, Some(1)));
+        assert_eq!(depths[&3], (3, Some(2)));
+        assert_eq!(depths[&4], (2, Some(1)));
+    }
+
+    #[test]
+    fn chain_depths_breaks_ties_on_lowest_id() {
+        let deps = graph(&[(1, &[]), (2, &[]), (3, &[2, 1])]);
+        assert_eq!(chain_depths(&deps)[&3], (2, Some(1)));
+    }
+
+    #[test]
+    fn chain_depths_ignores_dangling_edges() {
+        let deps = graph(&[(1, &[99])]);
+        assert_eq!(chain_depths(&deps)[&1], (1, None));
+    }
 }